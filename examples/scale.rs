@@ -0,0 +1,72 @@
+extern crate image;
+extern crate libplacebo;
+extern crate structopt;
+
+use libplacebo::colorspace::*;
+use libplacebo::context::*;
+use libplacebo::filter::*;
+use libplacebo::renderer::*;
+use libplacebo::vulkan::*;
+
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+struct CliArgs {
+    /// The image to be scaled
+    #[structopt(parse(from_os_str))]
+    input: PathBuf,
+    /// The file the result is written to
+    #[structopt(parse(from_os_str))]
+    output: PathBuf,
+    /// Output width
+    width: usize,
+    /// Output height
+    height: usize,
+}
+
+fn main() {
+    let args = CliArgs::from_args();
+
+    let input = image::open(&args.input).unwrap();
+
+    let context_params =
+        ContextParams::new(LogFunction::LogColor, LogLevel::LOG_WARN);
+    let ctx = Context::new(&context_params);
+
+    let vk_inst_params: VulkanInstanceParams = Default::default();
+    let vk_inst = VulkanInstance::new(&ctx, &vk_inst_params);
+
+    let mut vk_params: VulkanParams = Default::default();
+    vk_params.set_instance(vk_inst.instance());
+    vk_params.set_allow_software(true);
+    let vk = Vulkan::new(&ctx, &vk_params);
+    let gpu = vk.gpu();
+
+    let image = OwnedImage::from_dynamic_image(&gpu, &input).unwrap();
+
+    let fmt = gpu.find_named_fmt("rgba8").unwrap();
+    let target = RenderTarget::offscreen(
+        &gpu,
+        args.width,
+        args.height,
+        &fmt,
+        &ColorRepr::color_repr(&ColorReprs::Rgb),
+        &ColorSpace::color_space(&ColorSpaces::Srgb),
+    )
+    .unwrap();
+
+    let renderer = Renderer::new(&ctx, &gpu);
+    let mut params: RenderParams = Default::default();
+    params.set_upscaler(&FilterConfig::get_filter_config(
+        &FilterConfigs::EwaLanczos,
+    ));
+    params.set_downscaler(&FilterConfig::get_filter_config(
+        &FilterConfigs::Mitchell,
+    ));
+    renderer
+        .render_image(image.image(), &target, &params)
+        .unwrap();
+
+    target.to_rgba8_image().unwrap().save(&args.output).unwrap();
+}
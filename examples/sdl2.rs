@@ -10,9 +10,10 @@ use libplacebo::gpu::*;
 use libplacebo::renderer::*;
 use libplacebo::swapchain::*;
 use libplacebo::upload::*;
+use libplacebo::viewport::*;
 use libplacebo::vulkan::*;
 
-use sdl2::event::Event;
+use sdl2::event::{Event, WindowEvent};
 use sdl2::image::ImageRWops;
 use sdl2::keyboard::Keycode;
 use sdl2::pixels::PixelFormatEnum;
@@ -52,7 +53,7 @@ fn init_placebo() -> Context {
 fn init_vulkan(
     window: &mut Window,
     ctx: &mut Context,
-) -> (VulkanInstance, Vulkan, Swapchain) {
+) -> (VulkanInstance, Vulkan, SwapchainManager<'static>) {
     let vk_extensions = window.vulkan_instance_extensions().unwrap();
     let num = vk_extensions.len();
 
@@ -84,13 +85,10 @@ fn init_vulkan(
 
     let swapchain = Swapchain::new(&vk, &swapchain_params);
 
-    let (w, h) = swapchain.resize(WINDOW_WIDTH, WINDOW_HEIGHT);
+    let (w, h) = window.vulkan_drawable_size();
+    let manager = SwapchainManager::new(swapchain, w as usize, h as usize);
 
-    if w != WINDOW_WIDTH || h != WINDOW_HEIGHT {
-        println!("Note: window dimensions differ (got {}x{})", w, h);
-    }
-
-    (vk_inst, vk, swapchain)
+    (vk_inst, vk, manager)
 }
 
 fn upload_plane(
@@ -144,7 +142,7 @@ fn upload_plane(
     plane_data.data_from_mask(&mut masks);
 
     let gpu = vk.gpu();
-    plane_data.upload_plane(&gpu, plane, img_tex);
+    plane_data.upload_plane(&gpu, plane, img_tex).unwrap();
 
     Ok(())
 }
@@ -155,9 +153,7 @@ fn create_image(img_plane: &Plane) -> Image {
     let h = img_plane.height();
 
     let mut image: Image = Default::default();
-    let planes: [&Plane; 4] = [&img_plane; 4];
-    image.set_num_planes(1);
-    image.set_planes(&planes);
+    image.set_planes(&[img_plane.clone()]).unwrap();
     image.set_width(w);
     image.set_height(h);
 
@@ -207,7 +203,9 @@ fn render(renderer: &Renderer, image: &Image, target: &RenderTarget) {
     render_params.set_upscaler(&FilterConfig::get_filter_config(
         &FilterConfigs::EwaLanczos,
     ));
-    renderer.render_image(&image, &target, &render_params);
+    renderer
+        .render_image(&image, &target, &render_params)
+        .unwrap();
 }
 
 fn main() -> std::io::Result<()> {
@@ -231,7 +229,7 @@ fn main() -> std::io::Result<()> {
         .unwrap();
 
     let mut ctx = init_placebo();
-    let (_vk_inst, mut vk, swapchain) = init_vulkan(&mut window, &mut ctx);
+    let (_vk_inst, mut vk, mut swapchain) = init_vulkan(&mut window, &mut ctx);
 
     let gpu = vk.gpu();
     let mut img_tex = Tex::default(&gpu);
@@ -255,11 +253,14 @@ fn main() -> std::io::Result<()> {
     let w = img_plane.width();
     let h = img_plane.height();
     window.set_size(w as u32, h as u32).unwrap();
+    let (w, h) = window.vulkan_drawable_size();
+    swapchain.set_size(w as usize, h as usize);
 
     let mut last = Instant::now();
     let mut frames = 0;
     println!("Took {} ms for initialization", (last - start).as_millis());
 
+    let viewport = Viewport::new(ViewportMode::Fit);
     let mut event_pump = sdl_context.event_pump().unwrap();
 
     'running: loop {
@@ -270,20 +271,33 @@ fn main() -> std::io::Result<()> {
                     keycode: Some(Keycode::Escape),
                     ..
                 } => break 'running,
+                Event::Window {
+                    win_event: WindowEvent::SizeChanged(..),
+                    ..
+                } => {
+                    let (w, h) = window.vulkan_drawable_size();
+                    swapchain.set_size(w as usize, h as usize);
+                }
                 _ => {}
             }
         }
 
         let mut frame: SwapchainFrame = Default::default();
-        let ok = swapchain.start_frame(&mut frame);
-
-        if !ok {
-            sleep(Duration::from_millis(10));
-            continue;
+        match swapchain.start_frame(&mut frame) {
+            SwapchainEvent::OutOfDate => {
+                sleep(Duration::from_millis(10));
+                continue;
+            }
+            SwapchainEvent::Resized(w, h) => {
+                println!("Swapchain resized to {}x{}", w, h);
+            }
+            SwapchainEvent::Ready | SwapchainEvent::Suboptimal => {}
         }
 
-        let image = create_image(&img_plane);
+        let mut image = create_image(&img_plane);
         let mut target = create_target(&frame, &mut icc_profile);
+        viewport.apply(&mut image, &mut target);
+        viewport.clear_border(&gpu, &target).unwrap();
 
         if osd {
             set_osd(&image, &mut target, &osd_plane);
@@ -291,7 +305,9 @@ fn main() -> std::io::Result<()> {
 
         render(&renderer, &image, &target);
 
-        swapchain.submit_frame();
+        if !swapchain.submit_frame() {
+            continue;
+        }
         swapchain.swap_buffers();
         frames += 1;
 
@@ -304,6 +320,13 @@ fn main() -> std::io::Result<()> {
                 millis,
                 1000.0 * f64::from(frames) / millis as f64
             );
+            let stats = swapchain.stats();
+            println!(
+                "{} missed, {} suboptimal, refresh interval {:?}",
+                stats.missed_frames,
+                stats.suboptimal_frames,
+                stats.refresh_interval
+            );
             last = now;
             frames = 0;
         }
@@ -0,0 +1,167 @@
+extern crate libplacebo;
+extern crate structopt;
+
+use libplacebo::colorspace::*;
+use libplacebo::context::*;
+use libplacebo::planar::*;
+use libplacebo::renderer::*;
+use libplacebo::shaders::custom::*;
+use libplacebo::vulkan::*;
+use libplacebo::y4m::*;
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+struct CliArgs {
+    /// The Y4M stream to be processed, - for stdin
+    #[structopt(parse(from_os_str))]
+    input: PathBuf,
+    /// The file the result is written to, - for stdout
+    #[structopt(parse(from_os_str))]
+    output: PathBuf,
+    /// Output width
+    width: usize,
+    /// Output height
+    height: usize,
+    /// Use the high quality render profile
+    #[structopt(long)]
+    high_quality: bool,
+    /// Write packed 8-bit RGB frames instead of a Y4M stream
+    #[structopt(long)]
+    rgb: bool,
+    /// mpv user shaders to apply
+    #[structopt(long, parse(from_os_str))]
+    shader: Vec<PathBuf>,
+}
+
+fn open_input(path: &PathBuf) -> io::Result<Box<dyn BufRead>> {
+    if path.to_str() == Some("-") {
+        Ok(Box::new(BufReader::new(io::stdin())))
+    } else {
+        Ok(Box::new(BufReader::new(File::open(path)?)))
+    }
+}
+
+fn open_output(path: &PathBuf) -> io::Result<Box<dyn Write>> {
+    if path.to_str() == Some("-") {
+        Ok(Box::new(BufWriter::new(io::stdout())))
+    } else {
+        Ok(Box::new(BufWriter::new(File::create(path)?)))
+    }
+}
+
+enum Output {
+    Y4m(PlanarTarget, Y4mWriter<Box<dyn Write>>),
+    Rgb(RenderTarget, Box<dyn Write>),
+}
+
+fn main() -> io::Result<()> {
+    let args = CliArgs::from_args();
+
+    let mut reader = Y4mReader::new(open_input(&args.input)?)?;
+    let header = reader.header().clone();
+    let format = header.format()?;
+    let repr = header.repr()?;
+    let color = ColorSpace::color_space(&ColorSpaces::Bt709);
+
+    let context_params =
+        ContextParams::new(LogFunction::LogColor, LogLevel::LOG_WARN);
+    let ctx = Context::new(&context_params);
+
+    let vk_inst_params: VulkanInstanceParams = Default::default();
+    let vk_inst = VulkanInstance::new(&ctx, &vk_inst_params);
+
+    let mut vk_params: VulkanParams = Default::default();
+    vk_params.set_instance(vk_inst.instance());
+    vk_params.set_allow_software(true);
+    let vk = Vulkan::new(&ctx, &vk_params);
+    let gpu = vk.gpu();
+
+    let mut output = if args.rgb {
+        let fmt = gpu.find_named_fmt("rgba8").unwrap();
+        let target = RenderTarget::offscreen(
+            &gpu,
+            args.width,
+            args.height,
+            &fmt,
+            &ColorRepr::color_repr(&ColorReprs::Rgb),
+            &ColorSpace::color_space(&ColorSpaces::Srgb),
+        )
+        .unwrap();
+        Output::Rgb(target, open_output(&args.output)?)
+    } else {
+        // Keep the chroma siting of the input and tag the output with it
+        let location = header.chroma_location();
+        let mut out_header = header.clone();
+        out_header.width = args.width;
+        out_header.height = args.height;
+        out_header.set_chroma_location(&location)?;
+        let mut target = PlanarTarget::new(
+            &gpu,
+            format,
+            args.width,
+            args.height,
+            &out_header.repr()?,
+            &color,
+        )
+        .map_err(|err| io::Error::new(io::ErrorKind::Other, err.to_string()))?;
+        target.set_chroma_location(&location);
+        let writer = Y4mWriter::new(open_output(&args.output)?, &out_header)?;
+        Output::Y4m(target, writer)
+    };
+
+    let renderer = Renderer::new(&ctx, &gpu);
+    let mut params = if args.high_quality {
+        RenderParams::render_params(&RenderProfiles::HighQuality)
+    } else {
+        RenderParams::render_params(&RenderProfiles::Default)
+    };
+    for path in &args.shader {
+        let shader = UserShader::from_file(&ctx, &gpu, path).unwrap();
+        params.add_user_shader(shader);
+    }
+
+    let mut image = OwnedImage::new(&gpu, format);
+    let mut frames = 0;
+
+    while let Some(planes) = reader.read_frame()? {
+        let data: Vec<&[u8]> = planes.iter().map(|p| p.as_slice()).collect();
+        image
+            .upload(&data, header.width, header.height, &repr, &color)
+            .unwrap();
+        image
+            .image_mut()
+            .set_chroma_location(&header.chroma_location());
+
+        match &mut output {
+            Output::Y4m(target, writer) => {
+                target.render(&renderer, image.image(), &params).unwrap();
+                writer.write_frame(&target.download().unwrap())?;
+            }
+            Output::Rgb(target, writer) => {
+                renderer
+                    .render_image(image.image(), target, &params)
+                    .unwrap();
+                let rgba = target.download_rgba8().unwrap();
+                for texel in rgba.chunks_exact(4) {
+                    writer.write_all(&texel[..3])?;
+                }
+            }
+        }
+        frames += 1;
+    }
+
+    match &mut output {
+        Output::Y4m(_, writer) => writer.flush()?,
+        Output::Rgb(_, writer) => writer.flush()?,
+    }
+    eprintln!("Processed {} frames", frames);
+    for stage in renderer.errors().disabled() {
+        eprintln!("Disabled by the renderer: {}", stage);
+    }
+
+    Ok(())
+}
@@ -1,6 +1,12 @@
 // Tier  1
 #include <libplacebo/vulkan.h>
 
+// Tier 2
+#include <libplacebo/dispatch.h>
+
+// Tier 3
+#include <libplacebo/shaders/custom.h>
+
 // Tier 4
 #include <libplacebo/renderer.h>
 #include <libplacebo/utils/upload.h>
@@ -0,0 +1,248 @@
+use crate::colorspace::*;
+use crate::error::*;
+use crate::format::*;
+use crate::gpu::*;
+use crate::planar::*;
+use crate::renderer::*;
+
+use av_data::frame::{Frame, FrameBuffer, MediaKind, VideoInfo};
+use av_data::pixel::{self, ColorModel, Formaton, TrichromaticEncodingSystem};
+use av_data::pixel::{YUVRange, YUVSystem};
+
+fn video_info(frame: &Frame) -> Result<&VideoInfo> {
+    match &frame.kind {
+        MediaKind::Video(info) => Ok(info),
+        _ => Err(Error::InvalidFrame("not a video frame".to_owned())),
+    }
+}
+
+fn unsupported(format: &Formaton) -> Error {
+    Error::UnsupportedFormat(format!("{:?}", format))
+}
+
+pub fn pixel_format(format: &Formaton) -> Result<PixelFormat> {
+    use PixelFormat::*;
+
+    if format.be || format.palette {
+        return Err(unsupported(format));
+    }
+
+    let chromatons: Vec<_> = format.iter().flatten().collect();
+    let depth = chromatons.first().map_or(0, |c| c.depth);
+
+    match format.model {
+        ColorModel::Trichromatic(TrichromaticEncodingSystem::YUV(_)) => {
+            if chromatons.len() == 1 {
+                return match depth {
+                    8 => Ok(Gray),
+                    16 => Ok(Gray16),
+                    _ => Err(unsupported(format)),
+                };
+            }
+            if chromatons.len() != 3 || chromatons.iter().any(|c| c.packed) {
+                return Err(unsupported(format));
+            }
+            let ss = (chromatons[1].h_ss, chromatons[1].v_ss);
+            match (ss, depth) {
+                ((1, 1), 8) => Ok(Yuv420p),
+                ((1, 0), 8) => Ok(Yuv422p),
+                ((0, 0), 8) => Ok(Yuv444p),
+                ((1, 1), 10) => Ok(Yuv420p10),
+                ((1, 0), 10) => Ok(Yuv422p10),
+                ((0, 0), 10) => Ok(Yuv444p10),
+                ((1, 1), 12) => Ok(Yuv420p12),
+                ((1, 0), 12) => Ok(Yuv422p12),
+                ((0, 0), 12) => Ok(Yuv444p12),
+                ((1, 1), 16) => Ok(Yuv420p16),
+                ((1, 0), 16) => Ok(Yuv422p16),
+                ((0, 0), 16) => Ok(Yuv444p16),
+                _ => Err(unsupported(format)),
+            }
+        }
+        ColorModel::Trichromatic(TrichromaticEncodingSystem::RGB) => {
+            if depth != 8 || chromatons.iter().any(|c| !c.packed) {
+                return Err(unsupported(format));
+            }
+            let bgr = chromatons[0].comp_offs == 2;
+            match (format.elem_size, format.alpha, bgr) {
+                (3, false, false) => Ok(Rgb8),
+                (4, true, false) => Ok(Rgba8),
+                (4, true, true) => Ok(Bgra8),
+                _ => Err(unsupported(format)),
+            }
+        }
+        _ => Err(unsupported(format)),
+    }
+}
+
+pub fn color_repr(format: &Formaton) -> Result<ColorRepr> {
+    use pixel::MatrixCoefficients as MC;
+
+    let mut repr = pixel_format(format)?.default_repr();
+
+    let sys = match format.mc {
+        MC::Identity => Some(ColorSystem::COLOR_SYSTEM_RGB),
+        MC::BT709 => Some(ColorSystem::COLOR_SYSTEM_BT_709),
+        MC::BT470BG | MC::ST170M => Some(ColorSystem::COLOR_SYSTEM_BT_601),
+        MC::ST240M => Some(ColorSystem::COLOR_SYSTEM_SMPTE_240M),
+        MC::YCgCo => Some(ColorSystem::COLOR_SYSTEM_YCGCO),
+        MC::BT2020NonConstantLuminance => {
+            Some(ColorSystem::COLOR_SYSTEM_BT_2020_NC)
+        }
+        MC::BT2020ConstantLuminance => {
+            Some(ColorSystem::COLOR_SYSTEM_BT_2020_C)
+        }
+        _ => None,
+    };
+    if let Some(sys) = sys {
+        repr.set_sys(&sys);
+    }
+
+    if let ColorModel::Trichromatic(TrichromaticEncodingSystem::YUV(
+        YUVSystem::YCbCr(range),
+    )) = format.model
+    {
+        repr.set_levels(&match range {
+            YUVRange::Limited => ColorLevels::COLOR_LEVELS_TV,
+            YUVRange::Full => ColorLevels::COLOR_LEVELS_PC,
+        });
+    }
+
+    Ok(repr)
+}
+
+pub fn color_space(format: &Formaton) -> ColorSpace {
+    use pixel::ColorPrimaries as CP;
+    use pixel::TransferCharacteristic as TC;
+
+    let primaries = match format.primaries {
+        CP::BT709 => ColorPrimaries::COLOR_PRIM_BT_709,
+        CP::BT470M => ColorPrimaries::COLOR_PRIM_BT_470M,
+        CP::BT470BG => ColorPrimaries::COLOR_PRIM_BT_601_625,
+        CP::ST170M | CP::ST240M => ColorPrimaries::COLOR_PRIM_BT_601_525,
+        CP::BT2020 => ColorPrimaries::COLOR_PRIM_BT_2020,
+        CP::ST428 => ColorPrimaries::COLOR_PRIM_CIE_1931,
+        CP::P3DCI => ColorPrimaries::COLOR_PRIM_DCI_P3,
+        CP::P3Display => ColorPrimaries::COLOR_PRIM_DISPLAY_P3,
+        _ => ColorPrimaries::COLOR_PRIM_UNKNOWN,
+    };
+
+    let transfer = match format.xfer {
+        TC::BT1886
+        | TC::ST170M
+        | TC::ST240M
+        | TC::BT2020Ten
+        | TC::BT2020Twelve => ColorTransfer::COLOR_TRC_BT_1886,
+        TC::BT470M => ColorTransfer::COLOR_TRC_GAMMA22,
+        TC::BT470BG => ColorTransfer::COLOR_TRC_GAMMA28,
+        TC::Linear => ColorTransfer::COLOR_TRC_LINEAR,
+        TC::SRGB => ColorTransfer::COLOR_TRC_SRGB,
+        TC::PerceptualQuantizer => ColorTransfer::COLOR_TRC_PQ,
+        TC::HybridLogGamma => ColorTransfer::COLOR_TRC_HLG,
+        _ => ColorTransfer::COLOR_TRC_UNKNOWN,
+    };
+
+    ColorSpace::new(
+        &primaries,
+        &transfer,
+        &ColorLight::COLOR_LIGHT_UNKNOWN,
+        0.0,
+        0.0,
+        0.0,
+    )
+}
+
+pub fn chroma_location(format: &Formaton) -> ChromaLocation {
+    use pixel::ChromaLocation as CL;
+
+    match format.chroma_location {
+        CL::Left => ChromaLocation::CHROMA_LEFT,
+        CL::Center => ChromaLocation::CHROMA_CENTER,
+        CL::TopLeft => ChromaLocation::CHROMA_TOP_LEFT,
+        CL::Top => ChromaLocation::CHROMA_TOP_CENTER,
+        CL::BottomLeft => ChromaLocation::CHROMA_BOTTOM_LEFT,
+        CL::Bottom => ChromaLocation::CHROMA_BOTTOM_CENTER,
+        _ => ChromaLocation::CHROMA_UNKNOWN,
+    }
+}
+
+fn frame_error<E: std::fmt::Debug>(err: E) -> Error {
+    Error::InvalidFrame(format!("{:?}", err))
+}
+
+impl OwnedImage {
+    pub fn from_frame(gpu: &Gpu, frame: &Frame) -> Result<Self> {
+        let info = video_info(frame)?;
+        let mut image = OwnedImage::new(gpu, pixel_format(&info.format)?);
+        image.upload_frame(frame)?;
+        Ok(image)
+    }
+
+    // Reuses the textures of the previous upload when the size is unchanged
+    pub fn upload_frame(&mut self, frame: &Frame) -> Result<()> {
+        let info = video_info(frame)?;
+        let format = pixel_format(&info.format)?;
+        if format != self.format() {
+            return Err(Error::UnsupportedFormat(format!("{:?}", format)));
+        }
+
+        let num_planes = format.num_planes();
+        let mut data = Vec::with_capacity(num_planes);
+        let mut strides = Vec::with_capacity(num_planes);
+        for i in 0..num_planes {
+            data.push(frame.buf.as_slice_inner(i).map_err(frame_error)?);
+            strides.push(frame.buf.linesize(i).map_err(frame_error)?);
+        }
+
+        self.upload_with_strides(
+            &data,
+            &strides,
+            info.width,
+            info.height,
+            &color_repr(&info.format)?,
+            &color_space(&info.format),
+        )?;
+        self.image_mut()
+            .set_chroma_location(&chroma_location(&info.format));
+
+        Ok(())
+    }
+}
+
+impl PlanarTarget {
+    pub fn download_frame(&self, frame: &mut Frame) -> Result<()> {
+        let info = video_info(frame)?;
+        let format = pixel_format(&info.format)?;
+        if format != self.format() {
+            return Err(Error::UnsupportedFormat(format!("{:?}", format)));
+        }
+        if (info.width, info.height) != self.size() {
+            return Err(Error::InvalidFrame(format!(
+                "frame is {}x{}, the target {}x{}",
+                info.width,
+                info.height,
+                self.size().0,
+                self.size().1
+            )));
+        }
+
+        let num_planes = format.num_planes();
+        let mut strides = Vec::with_capacity(num_planes);
+        for i in 0..num_planes {
+            strides.push(frame.buf.linesize(i).map_err(frame_error)?);
+        }
+
+        let mut planes = self.download()?;
+        let tight = format.default_strides(info.width);
+        for (i, plane) in planes.iter_mut().enumerate() {
+            let dst = frame.buf.as_mut_slice_inner(i).map_err(frame_error)?;
+            for (dst, src) in
+                dst.chunks_mut(strides[i]).zip(plane.chunks(tight[i]))
+            {
+                dst[..src.len()].copy_from_slice(src);
+            }
+        }
+
+        Ok(())
+    }
+}
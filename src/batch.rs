@@ -0,0 +1,260 @@
+use crate::colorspace::*;
+use crate::context::*;
+use crate::error::*;
+use crate::format::*;
+use crate::gpu::*;
+use crate::renderer::*;
+
+use std::collections::VecDeque;
+
+// A rendered frame read back from the GPU, in the texel layout of the target
+// format with tightly packed rows
+pub struct BatchFrame {
+    pub index: u64,
+    pub width: usize,
+    pub height: usize,
+    pub data: Vec<u8>,
+}
+
+struct Slot {
+    image: OwnedImage,
+    upload: Option<Buf>,
+    target: RenderTarget,
+    readback: Buf,
+    index: u64,
+}
+
+// Keeps up to `depth` frames in flight, so that the upload of a frame, the
+// rendering of the previous one and the readback of older ones overlap.
+// Uploads and readbacks go through buffers, enabling `async_transfer` and
+// `async_compute` in `VulkanParams` lets them run on separate queues.
+pub struct BatchRenderer<'ctx> {
+    gpu: Gpu,
+    renderer: Renderer<'ctx>,
+    params: RenderParams,
+    format: PixelFormat,
+    slots: Vec<Slot>,
+    free: Vec<usize>,
+    pending: VecDeque<usize>,
+    finished: VecDeque<BatchFrame>,
+    next_index: u64,
+    width: usize,
+    height: usize,
+}
+
+impl<'ctx> BatchRenderer<'ctx> {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        ctx: &'ctx Context,
+        gpu: &Gpu,
+        format: PixelFormat,
+        depth: usize,
+        width: usize,
+        height: usize,
+        target_fmt: &Fmt,
+        target_repr: &ColorRepr,
+        target_color: &ColorSpace,
+    ) -> Result<Self> {
+        if width == 0 || height == 0 {
+            return Err(Error::InvalidDimensions { width, height });
+        }
+
+        let mut readback_params: BufParams = Default::default();
+        readback_params.set_type_(&BufType::BUF_TEX_TRANSFER);
+        readback_params.set_size(width * height * target_fmt.texel_size());
+        readback_params.set_host_readable(true);
+
+        let mut slots = Vec::with_capacity(depth.max(1));
+        for _ in 0..depth.max(1) {
+            slots.push(Slot {
+                image: OwnedImage::new(gpu, format),
+                upload: None,
+                target: RenderTarget::offscreen(
+                    gpu,
+                    width,
+                    height,
+                    target_fmt,
+                    target_repr,
+                    target_color,
+                )?,
+                readback: Buf::try_new(gpu, &readback_params)?,
+                index: 0,
+            });
+        }
+
+        Ok(BatchRenderer {
+            gpu: gpu.clone(),
+            renderer: Renderer::new(ctx, gpu),
+            params: Default::default(),
+            format,
+            free: (0..slots.len()).rev().collect(),
+            slots,
+            pending: VecDeque::new(),
+            finished: VecDeque::new(),
+            next_index: 0,
+            width,
+            height,
+        })
+    }
+
+    pub fn set_params(&mut self, params: &RenderParams) {
+        self.params = params.clone();
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.pending.len()
+    }
+
+    // Queues a frame and returns its index. When every slot is busy the
+    // oldest frame is waited for and kept until it is polled.
+    pub fn submit(
+        &mut self,
+        data: &[&[u8]],
+        width: usize,
+        height: usize,
+        repr: &ColorRepr,
+        color: &ColorSpace,
+    ) -> Result<u64> {
+        let num_planes = self.format.num_planes();
+        if data.len() != num_planes {
+            return Err(Error::PlaneCountMismatch {
+                expected: num_planes,
+                got: data.len(),
+            });
+        }
+
+        if self.free.is_empty() {
+            let frame = self.wait_oldest()?;
+            self.finished.push_back(frame);
+        }
+        let slot_index = self.free.pop().unwrap();
+
+        if let Err(err) =
+            self.queue_frame(slot_index, data, width, height, repr, color)
+        {
+            self.free.push(slot_index);
+            return Err(err);
+        }
+
+        let slot = &mut self.slots[slot_index];
+        slot.index = self.next_index;
+        self.next_index += 1;
+        self.pending.push_back(slot_index);
+
+        Ok(slot.index)
+    }
+
+    fn queue_frame(
+        &mut self,
+        slot_index: usize,
+        data: &[&[u8]],
+        width: usize,
+        height: usize,
+        repr: &ColorRepr,
+        color: &ColorSpace,
+    ) -> Result<()> {
+        let slot = &mut self.slots[slot_index];
+
+        let strides = self.format.default_strides(width);
+        let ranges = self.format.plane_ranges(height, &strides);
+        for (plane, (_, needed)) in data.iter().zip(&ranges) {
+            if plane.len() < *needed {
+                return Err(Error::DataTooShort {
+                    len: plane.len(),
+                    needed: *needed,
+                });
+            }
+        }
+        let size = self.format.buffer_size(height, &strides);
+
+        let reuse = slot.upload.as_ref().map_or(false, |b| b.size() >= size);
+        if !reuse {
+            let mut params: BufParams = Default::default();
+            params.set_type_(&BufType::BUF_TEX_TRANSFER);
+            params.set_size(size);
+            params.set_host_writable(true);
+            slot.upload = Some(Buf::try_new(&self.gpu, &params)?);
+        }
+        let upload = slot.upload.as_ref().unwrap();
+
+        let mut plane_data = self.format.plane_data(width, height, &strides);
+        for (i, plane) in plane_data.iter_mut().enumerate() {
+            let (offset, plane_size) = ranges[i];
+            upload.write(offset, &data[i][..plane_size])?;
+            plane.set_buf(upload);
+            plane.set_buf_offset(offset);
+        }
+
+        slot.image
+            .upload_planes(&plane_data, width, height, repr, color)?;
+
+        self.renderer.render_image(
+            slot.image.image(),
+            &slot.target,
+            &self.params,
+        )?;
+
+        slot.target
+            .texture()
+            .ok_or(Error::NoTexture)?
+            .download_to_buf(&slot.readback, 0)?;
+        self.gpu.gpu_flush();
+
+        Ok(())
+    }
+
+    // Returns the oldest finished frame without blocking
+    pub fn poll(&mut self) -> Option<Result<BatchFrame>> {
+        if let Some(frame) = self.finished.pop_front() {
+            return Some(Ok(frame));
+        }
+
+        let oldest = *self.pending.front()?;
+        if self.slots[oldest].readback.poll(0) {
+            return None;
+        }
+        Some(self.wait_oldest())
+    }
+
+    // Waits for every frame in flight, in submission order
+    pub fn drain(&mut self) -> Drain<'_, 'ctx> {
+        Drain { batch: self }
+    }
+
+    fn wait_oldest(&mut self) -> Result<BatchFrame> {
+        let slot_index = self.pending.pop_front().unwrap();
+        let slot = &self.slots[slot_index];
+
+        while slot.readback.poll(u64::MAX) {}
+
+        let mut data = vec![0; slot.readback.size()];
+        let result = slot.readback.read(0, &mut data);
+        self.free.push(slot_index);
+        result?;
+
+        Ok(BatchFrame {
+            index: slot.index,
+            width: self.width,
+            height: self.height,
+            data,
+        })
+    }
+}
+
+pub struct Drain<'a, 'ctx> {
+    batch: &'a mut BatchRenderer<'ctx>,
+}
+
+impl<'a, 'ctx> Iterator for Drain<'a, 'ctx> {
+    type Item = Result<BatchFrame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(frame) = self.batch.finished.pop_front() {
+            return Some(Ok(frame));
+        }
+        if self.batch.pending.is_empty() {
+            return None;
+        }
+        Some(self.batch.wait_oldest())
+    }
+}
@@ -97,6 +97,21 @@ create_enum!(
     )
 );
 
+create_enum!(
+    ChromaLocation,
+    pl_chroma_location,
+    (
+        CHROMA_UNKNOWN,
+        CHROMA_LEFT,
+        CHROMA_CENTER,
+        CHROMA_TOP_LEFT,
+        CHROMA_TOP_CENTER,
+        CHROMA_BOTTOM_LEFT,
+        CHROMA_BOTTOM_CENTER,
+        CHROMA_COUNT,
+    )
+);
+
 create_enum!(
     RenderingIntent,
     pl_rendering_intent,
@@ -14,6 +14,32 @@ create_complete_struct!(
 
 internal_object!(Rect2D, rect2d, pl_rect2d);
 
+impl Rect2D {
+    pub fn x0(&self) -> usize {
+        self.rect2d.x0.max(0) as usize
+    }
+
+    pub fn y0(&self) -> usize {
+        self.rect2d.y0.max(0) as usize
+    }
+
+    pub fn x1(&self) -> usize {
+        self.rect2d.x1.max(0) as usize
+    }
+
+    pub fn y1(&self) -> usize {
+        self.rect2d.y1.max(0) as usize
+    }
+
+    pub fn width(&self) -> usize {
+        (self.rect2d.x1 - self.rect2d.x0).abs() as usize
+    }
+
+    pub fn height(&self) -> usize {
+        (self.rect2d.y1 - self.rect2d.y0).abs() as usize
+    }
+}
+
 create_complete_struct!(
     Rect2DF,
     rect2df,
@@ -26,6 +52,32 @@ create_complete_struct!(
 
 internal_object!(Rect2DF, rect2df, pl_rect2df);
 
+impl Rect2DF {
+    pub fn x0(&self) -> f32 {
+        self.rect2df.x0
+    }
+
+    pub fn y0(&self) -> f32 {
+        self.rect2df.y0
+    }
+
+    pub fn x1(&self) -> f32 {
+        self.rect2df.x1
+    }
+
+    pub fn y1(&self) -> f32 {
+        self.rect2df.y1
+    }
+
+    pub fn width(&self) -> f32 {
+        (self.rect2df.x1 - self.rect2df.x0).abs()
+    }
+
+    pub fn height(&self) -> f32 {
+        (self.rect2df.y1 - self.rect2df.y0).abs()
+    }
+}
+
 create_complete_struct!(
     Rect3D,
     rect3d,
@@ -1,7 +1,7 @@
 use crate::*;
 use libplacebo_sys::*;
 
-use std::ffi::c_void;
+use std::ffi::{c_void, CStr};
 use std::ptr::null_mut;
 
 create_enum!(
@@ -40,18 +40,43 @@ impl ContextParams {
     }
 }
 
+// Collects the messages logged while capturing at `level` or more severe,
+// passing every message on to the log callback the context was set up with
+struct LogCapture {
+    params: pl_context_params,
+    level: pl_log_level,
+    messages: Vec<String>,
+}
+
+unsafe extern "C" fn capture_log(
+    priv_: *mut c_void,
+    level: pl_log_level,
+    msg: *const i8,
+) {
+    let capture = &mut *(priv_ as *mut LogCapture);
+    if level as i32 <= capture.level as i32 {
+        let msg = CStr::from_ptr(msg).to_string_lossy();
+        capture.messages.push(msg.trim_end().to_owned());
+    }
+    if let Some(log_cb) = capture.params.log_cb {
+        if level as i32 <= capture.params.log_level as i32 {
+            log_cb(capture.params.log_priv, level, msg);
+        }
+    }
+}
+
 pub struct Context {
     ctx: *mut pl_context,
+    params: pl_context_params,
 }
 
 impl Default for Context {
     fn default() -> Self {
-        let ctx = unsafe {
-            pl_context_create(PL_API_VER as i32, &pl_context_default_params)
-        };
+        let params = unsafe { pl_context_default_params };
+        let ctx = unsafe { pl_context_create(PL_API_VER as i32, &params) };
         assert!(!ctx.is_null());
 
-        Context { ctx }
+        Context { ctx, params }
     }
 }
 
@@ -62,17 +87,59 @@ impl Context {
         };
         assert!(!ctx.is_null());
 
-        Context { ctx }
+        Context {
+            ctx,
+            params: params.ctx_params,
+        }
     }
 
     pub fn update(&mut self, ctx_params: Option<&ContextParams>) {
-        let mut par = unsafe { &pl_context_default_params };
-        if let Some(v) = ctx_params {
-            par = &v.ctx_params;
-        }
+        self.params = match ctx_params {
+            Some(v) => v.ctx_params,
+            None => unsafe { pl_context_default_params },
+        };
         unsafe {
-            pl_context_update(self.ctx, par);
+            pl_context_update(self.ctx, &self.params);
+        }
+    }
+
+    // Runs `f` and returns the errors libplacebo logged meanwhile, for the
+    // calls that only report what went wrong through the log
+    pub(crate) fn capture_errors<T>(
+        &self,
+        f: impl FnOnce() -> T,
+    ) -> (T, Vec<String>) {
+        self.capture_log(&LogLevel::LOG_ERR, f)
+    }
+
+    // Runs `f` and returns the messages logged meanwhile at `level` or more
+    // severe, whatever level the context logs at
+    pub(crate) fn capture_log<T>(
+        &self,
+        level: &LogLevel,
+        f: impl FnOnce() -> T,
+    ) -> (T, Vec<String>) {
+        let level = level.to_pl_log_level();
+        let mut capture = LogCapture {
+            params: self.params,
+            level,
+            messages: Vec::new(),
+        };
+        let mut params = self.params;
+        params.log_cb = Some(capture_log);
+        params.log_priv = &mut capture as *mut LogCapture as *mut c_void;
+        if (params.log_level as i32) < level as i32 {
+            params.log_level = level;
         }
+
+        let res = unsafe {
+            pl_context_update(self.ctx, &params);
+            let res = f();
+            pl_context_update(self.ctx, &self.params);
+            res
+        };
+
+        (res, capture.messages)
     }
 
     pub(crate) fn get_mut_ptr(&self) -> *mut pl_context {
@@ -0,0 +1,580 @@
+use crate::colorspace::*;
+use crate::error::*;
+use crate::format::*;
+use crate::gpu::*;
+use crate::upload::*;
+
+pub const fn fourcc(a: u8, b: u8, c: u8, d: u8) -> u32 {
+    (a as u32) | (b as u32) << 8 | (c as u32) << 16 | (d as u32) << 24
+}
+
+pub const DRM_FORMAT_R8: u32 = fourcc(b'R', b'8', b' ', b' ');
+pub const DRM_FORMAT_R16: u32 = fourcc(b'R', b'1', b'6', b' ');
+pub const DRM_FORMAT_GR88: u32 = fourcc(b'G', b'R', b'8', b'8');
+pub const DRM_FORMAT_RGB565: u32 = fourcc(b'R', b'G', b'1', b'6');
+pub const DRM_FORMAT_XRGB8888: u32 = fourcc(b'X', b'R', b'2', b'4');
+pub const DRM_FORMAT_XBGR8888: u32 = fourcc(b'X', b'B', b'2', b'4');
+pub const DRM_FORMAT_RGBX8888: u32 = fourcc(b'R', b'X', b'2', b'4');
+pub const DRM_FORMAT_BGRX8888: u32 = fourcc(b'B', b'X', b'2', b'4');
+pub const DRM_FORMAT_ARGB8888: u32 = fourcc(b'A', b'R', b'2', b'4');
+pub const DRM_FORMAT_ABGR8888: u32 = fourcc(b'A', b'B', b'2', b'4');
+pub const DRM_FORMAT_RGBA8888: u32 = fourcc(b'R', b'A', b'2', b'4');
+pub const DRM_FORMAT_BGRA8888: u32 = fourcc(b'B', b'A', b'2', b'4');
+pub const DRM_FORMAT_XRGB2101010: u32 = fourcc(b'X', b'R', b'3', b'0');
+pub const DRM_FORMAT_XBGR2101010: u32 = fourcc(b'X', b'B', b'3', b'0');
+pub const DRM_FORMAT_ARGB2101010: u32 = fourcc(b'A', b'R', b'3', b'0');
+pub const DRM_FORMAT_ABGR2101010: u32 = fourcc(b'A', b'B', b'3', b'0');
+pub const DRM_FORMAT_YUYV: u32 = fourcc(b'Y', b'U', b'Y', b'V');
+pub const DRM_FORMAT_YVYU: u32 = fourcc(b'Y', b'V', b'Y', b'U');
+pub const DRM_FORMAT_UYVY: u32 = fourcc(b'U', b'Y', b'V', b'Y');
+pub const DRM_FORMAT_VYUY: u32 = fourcc(b'V', b'Y', b'U', b'Y');
+pub const DRM_FORMAT_NV12: u32 = fourcc(b'N', b'V', b'1', b'2');
+pub const DRM_FORMAT_NV21: u32 = fourcc(b'N', b'V', b'2', b'1');
+pub const DRM_FORMAT_P010: u32 = fourcc(b'P', b'0', b'1', b'0');
+pub const DRM_FORMAT_P016: u32 = fourcc(b'P', b'0', b'1', b'6');
+pub const DRM_FORMAT_YUV420: u32 = fourcc(b'Y', b'U', b'1', b'2');
+pub const DRM_FORMAT_YVU420: u32 = fourcc(b'Y', b'V', b'1', b'2');
+pub const DRM_FORMAT_YUV422: u32 = fourcc(b'Y', b'U', b'1', b'6');
+pub const DRM_FORMAT_YUV444: u32 = fourcc(b'Y', b'U', b'2', b'4');
+
+pub const DRM_FORMAT_MOD_LINEAR: u64 = 0;
+pub const DRM_FORMAT_MOD_INVALID: u64 = 0x00ff_ffff_ffff_ffff;
+
+// One `PlaneData` of a DRM format: the memory plane it reads from, the bytes
+// per pixel and its components in memory order, each given as padding bits,
+// size in bits and the channel it maps to. Packed YCbCr formats are split
+// into a luma and two chroma planes reading the same memory.
+struct DrmPlane {
+    source: usize,
+    pixel_stride: usize,
+    components: &'static [(i32, i32, i32)],
+    subsampled: bool,
+}
+
+const fn plane(
+    source: usize,
+    pixel_stride: usize,
+    components: &'static [(i32, i32, i32)],
+    subsampled: bool,
+) -> DrmPlane {
+    DrmPlane {
+        source,
+        pixel_stride,
+        components,
+        subsampled,
+    }
+}
+
+fn fourcc_name(fourcc: u32) -> String {
+    fourcc
+        .to_le_bytes()
+        .iter()
+        .map(|b| *b as char)
+        .collect::<String>()
+        .trim_end()
+        .to_owned()
+}
+
+// A frame described the way KMS and V4L2 describe it, a fourcc, a modifier
+// and the offset and pitch of every memory plane
+#[derive(Clone, Debug, PartialEq)]
+pub struct DrmFrame {
+    fourcc: u32,
+    modifier: u64,
+    width: usize,
+    height: usize,
+    offsets: Vec<usize>,
+    pitches: Vec<usize>,
+}
+
+impl DrmFrame {
+    pub fn new(
+        fourcc: u32,
+        modifier: u64,
+        width: usize,
+        height: usize,
+        offsets: &[usize],
+        pitches: &[usize],
+    ) -> Self {
+        DrmFrame {
+            fourcc,
+            modifier,
+            width,
+            height,
+            offsets: offsets.to_vec(),
+            pitches: pitches.to_vec(),
+        }
+    }
+
+    pub fn fourcc(&self) -> u32 {
+        self.fourcc
+    }
+
+    pub fn modifier(&self) -> u64 {
+        self.modifier
+    }
+
+    pub fn size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    fn layout(&self) -> Result<Vec<DrmPlane>> {
+        const R: i32 = 0;
+        const G: i32 = 1;
+        const B: i32 = 2;
+        const A: i32 = 3;
+        const Y: i32 = 0;
+        const U: i32 = 1;
+        const V: i32 = 2;
+
+        let layout = match self.fourcc {
+            DRM_FORMAT_R8 => vec![plane(0, 1, &[(0, 8, R)], false)],
+            DRM_FORMAT_R16 => vec![plane(0, 2, &[(0, 16, R)], false)],
+            DRM_FORMAT_GR88 => {
+                vec![plane(0, 2, &[(0, 8, R), (0, 8, G)], false)]
+            }
+            DRM_FORMAT_RGB565 => {
+                vec![plane(0, 2, &[(0, 5, B), (0, 6, G), (0, 5, R)], false)]
+            }
+            DRM_FORMAT_XRGB8888 => {
+                vec![plane(0, 4, &[(0, 8, B), (0, 8, G), (0, 8, R)], false)]
+            }
+            DRM_FORMAT_XBGR8888 => {
+                vec![plane(0, 4, &[(0, 8, R), (0, 8, G), (0, 8, B)], false)]
+            }
+            DRM_FORMAT_RGBX8888 => {
+                vec![plane(0, 4, &[(8, 8, B), (0, 8, G), (0, 8, R)], false)]
+            }
+            DRM_FORMAT_BGRX8888 => {
+                vec![plane(0, 4, &[(8, 8, R), (0, 8, G), (0, 8, B)], false)]
+            }
+            DRM_FORMAT_ARGB8888 => vec![plane(
+                0,
+                4,
+                &[(0, 8, B), (0, 8, G), (0, 8, R), (0, 8, A)],
+                false,
+            )],
+            DRM_FORMAT_ABGR8888 => vec![plane(
+                0,
+                4,
+                &[(0, 8, R), (0, 8, G), (0, 8, B), (0, 8, A)],
+                false,
+            )],
+            DRM_FORMAT_RGBA8888 => vec![plane(
+                0,
+                4,
+                &[(0, 8, A), (0, 8, B), (0, 8, G), (0, 8, R)],
+                false,
+            )],
+            DRM_FORMAT_BGRA8888 => vec![plane(
+                0,
+                4,
+                &[(0, 8, A), (0, 8, R), (0, 8, G), (0, 8, B)],
+                false,
+            )],
+            DRM_FORMAT_XRGB2101010 => {
+                vec![plane(0, 4, &[(0, 10, B), (0, 10, G), (0, 10, R)], false)]
+            }
+            DRM_FORMAT_XBGR2101010 => {
+                vec![plane(0, 4, &[(0, 10, R), (0, 10, G), (0, 10, B)], false)]
+            }
+            DRM_FORMAT_ARGB2101010 => vec![plane(
+                0,
+                4,
+                &[(0, 10, B), (0, 10, G), (0, 10, R), (0, 2, A)],
+                false,
+            )],
+            DRM_FORMAT_ABGR2101010 => vec![plane(
+                0,
+                4,
+                &[(0, 10, R), (0, 10, G), (0, 10, B), (0, 2, A)],
+                false,
+            )],
+            DRM_FORMAT_YUYV => vec![
+                plane(0, 2, &[(0, 8, Y)], false),
+                plane(0, 4, &[(8, 8, U)], true),
+                plane(0, 4, &[(24, 8, V)], true),
+            ],
+            DRM_FORMAT_YVYU => vec![
+                plane(0, 2, &[(0, 8, Y)], false),
+                plane(0, 4, &[(24, 8, U)], true),
+                plane(0, 4, &[(8, 8, V)], true),
+            ],
+            DRM_FORMAT_UYVY => vec![
+                plane(0, 2, &[(8, 8, Y)], false),
+                plane(0, 4, &[(0, 8, U)], true),
+                plane(0, 4, &[(16, 8, V)], true),
+            ],
+            DRM_FORMAT_VYUY => vec![
+                plane(0, 2, &[(8, 8, Y)], false),
+                plane(0, 4, &[(16, 8, U)], true),
+                plane(0, 4, &[(0, 8, V)], true),
+            ],
+            DRM_FORMAT_NV12 => vec![
+                plane(0, 1, &[(0, 8, Y)], false),
+                plane(1, 2, &[(0, 8, U), (0, 8, V)], true),
+            ],
+            DRM_FORMAT_NV21 => vec![
+                plane(0, 1, &[(0, 8, Y)], false),
+                plane(1, 2, &[(0, 8, V), (0, 8, U)], true),
+            ],
+            DRM_FORMAT_P010 | DRM_FORMAT_P016 => vec![
+                plane(0, 2, &[(0, 16, Y)], false),
+                plane(1, 4, &[(0, 16, U), (0, 16, V)], true),
+            ],
+            DRM_FORMAT_YUV420 | DRM_FORMAT_YUV422 | DRM_FORMAT_YUV444 => vec![
+                plane(0, 1, &[(0, 8, Y)], false),
+                plane(1, 1, &[(0, 8, U)], true),
+                plane(2, 1, &[(0, 8, V)], true),
+            ],
+            DRM_FORMAT_YVU420 => vec![
+                plane(0, 1, &[(0, 8, Y)], false),
+                plane(1, 1, &[(0, 8, V)], true),
+                plane(2, 1, &[(0, 8, U)], true),
+            ],
+            _ => {
+                return Err(Error::UnsupportedFormat(fourcc_name(self.fourcc)))
+            }
+        };
+
+        Ok(layout)
+    }
+
+    // Number of memory planes the layout reads from and log2 of the
+    // horizontal and vertical chroma subsampling factors
+    fn plane_geometry(&self) -> Result<(usize, (usize, usize))> {
+        let layout = self.layout()?;
+        let sources = layout.iter().map(|p| p.source + 1).max().unwrap_or(0);
+        let shift = match self.fourcc {
+            DRM_FORMAT_NV12 | DRM_FORMAT_NV21 | DRM_FORMAT_P010
+            | DRM_FORMAT_P016 | DRM_FORMAT_YUV420 | DRM_FORMAT_YVU420 => (1, 1),
+            DRM_FORMAT_YUYV | DRM_FORMAT_YVYU | DRM_FORMAT_UYVY
+            | DRM_FORMAT_VYUY | DRM_FORMAT_YUV422 => (1, 0),
+            _ => (0, 0),
+        };
+
+        Ok((sources, shift))
+    }
+
+    pub fn is_yuv(&self) -> bool {
+        self.layout().map_or(false, |layout| layout.len() > 1)
+    }
+
+    pub fn has_alpha(&self) -> bool {
+        self.layout().map_or(false, |layout| {
+            layout[0].components.iter().any(|c| c.2 == 3)
+        })
+    }
+
+    // A `PixelFormat` with the same planes, components and subsampling, to
+    // create the `OwnedImage` the planes are uploaded to. None for formats
+    // it can't describe, like RGB565 or padded RGB.
+    pub fn pixel_format(&self) -> Option<PixelFormat> {
+        let format = match self.fourcc {
+            DRM_FORMAT_R8 => PixelFormat::Gray,
+            DRM_FORMAT_R16 => PixelFormat::Gray16,
+            DRM_FORMAT_ABGR8888 => PixelFormat::Rgba8,
+            DRM_FORMAT_ARGB8888 => PixelFormat::Bgra8,
+            DRM_FORMAT_NV12 | DRM_FORMAT_NV21 => PixelFormat::Nv12,
+            DRM_FORMAT_P010 | DRM_FORMAT_P016 => PixelFormat::P010,
+            DRM_FORMAT_YUV420 | DRM_FORMAT_YVU420 => PixelFormat::Yuv420p,
+            DRM_FORMAT_YUYV | DRM_FORMAT_YVYU | DRM_FORMAT_UYVY
+            | DRM_FORMAT_VYUY | DRM_FORMAT_YUV422 => PixelFormat::Yuv422p,
+            DRM_FORMAT_YUV444 => PixelFormat::Yuv444p,
+            _ => return None,
+        };
+
+        Some(format)
+    }
+
+    // Default color representation of the format. Alpha in DRM formats is
+    // premultiplied.
+    pub fn color_repr(&self) -> Result<ColorRepr> {
+        self.layout()?;
+
+        let bits = match self.fourcc {
+            DRM_FORMAT_P010 => BitEncoding::new(16, 10, 6),
+            DRM_FORMAT_P016 => BitEncoding::new(16, 16, 0),
+            _ => Default::default(),
+        };
+        let alpha = if self.has_alpha() {
+            AlphaMode::ALPHA_PREMULTIPLIED
+        } else {
+            AlphaMode::ALPHA_UNKNOWN
+        };
+
+        let repr = if self.is_yuv() {
+            ColorRepr::new(
+                &ColorSystem::COLOR_SYSTEM_BT_709,
+                &ColorLevels::COLOR_LEVELS_TV,
+                &alpha,
+                &bits,
+            )
+        } else {
+            ColorRepr::new(
+                &ColorSystem::COLOR_SYSTEM_RGB,
+                &ColorLevels::COLOR_LEVELS_PC,
+                &alpha,
+                &bits,
+            )
+        };
+
+        Ok(repr)
+    }
+
+    // Describes the planes, each paired with its offset in the memory of the
+    // frame. Only linear buffers can be uploaded from, tiled modifiers are
+    // rejected.
+    fn plane_data<'a>(&self) -> Result<Vec<(PlaneData<'a>, usize)>> {
+        if self.modifier != DRM_FORMAT_MOD_LINEAR {
+            return Err(Error::UnsupportedModifier(self.modifier));
+        }
+        if self.width == 0 || self.height == 0 {
+            return Err(Error::InvalidDimensions {
+                width: self.width,
+                height: self.height,
+            });
+        }
+
+        let layout = self.layout()?;
+        let (sources, (sx, sy)) = self.plane_geometry()?;
+        if self.offsets.len() < sources || self.pitches.len() < sources {
+            return Err(Error::PlaneCountMismatch {
+                expected: sources,
+                got: self.offsets.len().min(self.pitches.len()),
+            });
+        }
+
+        Ok(layout
+            .iter()
+            .map(|plane| {
+                let (w, h) = if plane.subsampled {
+                    (
+                        (self.width + (1 << sx) - 1) >> sx,
+                        (self.height + (1 << sy) - 1) >> sy,
+                    )
+                } else {
+                    (self.width, self.height)
+                };
+
+                let mut component_pad = [0; 4];
+                let mut component_size = [0; 4];
+                let mut component_map = [0; 4];
+                for (c, (pad, size, map)) in plane.components.iter().enumerate()
+                {
+                    component_pad[c] = *pad;
+                    component_size[c] = *size;
+                    component_map[c] = *map;
+                }
+
+                let mut data: PlaneData = Default::default();
+                data.set_type_(&FmtType::FMT_UNORM);
+                data.set_width(w);
+                data.set_height(h);
+                data.set_component_size(&component_size);
+                data.set_component_pad(&component_pad);
+                data.set_component_map(&component_map);
+                data.set_pixel_stride(plane.pixel_stride);
+                data.set_row_stride(self.pitches[plane.source]);
+
+                (data, self.offsets[plane.source])
+            })
+            .collect())
+    }
+
+    // Planes reading from a frame in CPU memory, `offsets` are relative to
+    // the start of `data`
+    pub fn from_memory<'a>(
+        &self,
+        data: &'a [u8],
+    ) -> Result<Vec<PlaneData<'a>>> {
+        self.plane_data()?
+            .into_iter()
+            .map(|(mut plane, offset)| {
+                if offset > data.len() {
+                    return Err(Error::DataTooShort {
+                        len: data.len(),
+                        needed: offset,
+                    });
+                }
+                plane.set_pixels(&data[offset..]);
+                plane.validate()?;
+                Ok(plane)
+            })
+            .collect()
+    }
+
+    // Planes reading from a buffer, e.g. one imported from a dma-buf
+    pub fn from_buf<'a>(&self, buf: &'a Buf) -> Result<Vec<PlaneData<'a>>> {
+        self.plane_data()?
+            .into_iter()
+            .map(|(mut plane, offset)| {
+                plane.set_buf(buf);
+                plane.set_buf_offset(offset);
+                plane.validate()?;
+                Ok(plane)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn linear(fourcc: u32, width: usize, height: usize) -> DrmFrame {
+        DrmFrame::new(fourcc, DRM_FORMAT_MOD_LINEAR, width, height, &[], &[])
+    }
+
+    #[test]
+    fn fourcc_is_little_endian() {
+        assert_eq!(DRM_FORMAT_NV12, 0x3231_564e);
+        assert_eq!(DRM_FORMAT_XRGB8888, 0x3432_5258);
+        assert_eq!(fourcc_name(DRM_FORMAT_R8), "R8");
+        assert_eq!(fourcc_name(DRM_FORMAT_P010), "P010");
+    }
+
+    #[test]
+    fn unknown_fourccs_are_rejected() {
+        let frame = linear(fourcc(b'Z', b'Z', b'9', b' '), 4, 4);
+
+        assert!(!frame.is_yuv());
+        assert!(frame.pixel_format().is_none());
+        assert_eq!(
+            frame.color_repr().err(),
+            Some(Error::UnsupportedFormat("ZZ9".to_owned()))
+        );
+    }
+
+    #[test]
+    fn formats_are_classified() {
+        assert!(linear(DRM_FORMAT_NV12, 4, 4).is_yuv());
+        assert!(linear(DRM_FORMAT_YUYV, 4, 4).is_yuv());
+        assert!(!linear(DRM_FORMAT_XRGB8888, 4, 4).is_yuv());
+        assert!(linear(DRM_FORMAT_ARGB2101010, 4, 4).has_alpha());
+        assert!(!linear(DRM_FORMAT_XBGR8888, 4, 4).has_alpha());
+
+        assert_eq!(
+            linear(DRM_FORMAT_NV21, 4, 4).plane_geometry(),
+            Ok((2, (1, 1)))
+        );
+        assert_eq!(
+            linear(DRM_FORMAT_UYVY, 4, 4).plane_geometry(),
+            Ok((1, (1, 0)))
+        );
+        assert_eq!(
+            linear(DRM_FORMAT_YUV444, 4, 4).plane_geometry(),
+            Ok((3, (0, 0)))
+        );
+    }
+
+    #[test]
+    fn pixel_formats_match_exactly() {
+        let format = |fourcc| linear(fourcc, 4, 4).pixel_format();
+
+        assert_eq!(format(DRM_FORMAT_R8), Some(PixelFormat::Gray));
+        assert_eq!(format(DRM_FORMAT_ABGR8888), Some(PixelFormat::Rgba8));
+        assert_eq!(format(DRM_FORMAT_ARGB8888), Some(PixelFormat::Bgra8));
+        assert_eq!(format(DRM_FORMAT_YUYV), Some(PixelFormat::Yuv422p));
+        assert_eq!(format(DRM_FORMAT_GR88), None);
+        assert_eq!(format(DRM_FORMAT_RGB565), None);
+        assert_eq!(format(DRM_FORMAT_XRGB8888), None);
+        assert_eq!(format(DRM_FORMAT_ABGR2101010), None);
+    }
+
+    #[test]
+    fn planes_follow_pitches_and_offsets() {
+        let frame = DrmFrame::new(
+            DRM_FORMAT_NV12,
+            DRM_FORMAT_MOD_LINEAR,
+            5,
+            3,
+            &[0, 24],
+            &[8, 8],
+        );
+        let planes = frame.plane_data().unwrap();
+
+        assert_eq!(planes.len(), 2);
+        let (luma, offset) = &planes[0];
+        assert_eq!((luma.width(), luma.height(), *offset), (5, 3, 0));
+        assert_eq!(luma.data_size(), 21);
+        assert_eq!(luma.layout(), "PL_FMT_UNORM 8 map [0]");
+        let (chroma, offset) = &planes[1];
+        assert_eq!((chroma.width(), chroma.height(), *offset), (3, 2, 24));
+        assert_eq!(chroma.data_size(), 14);
+        assert_eq!(chroma.layout(), "PL_FMT_UNORM 8:8 map [1, 2]");
+
+        let memory = [0u8; 38];
+        assert_eq!(frame.from_memory(&memory).map(|p| p.len()), Ok(2));
+        assert_eq!(
+            frame.from_memory(&memory[..37]).err(),
+            Some(Error::DataTooShort {
+                len: 13,
+                needed: 14
+            })
+        );
+    }
+
+    #[test]
+    fn packed_yuv_splits_into_planes() {
+        let frame = DrmFrame::new(
+            DRM_FORMAT_YUYV,
+            DRM_FORMAT_MOD_LINEAR,
+            5,
+            2,
+            &[0],
+            &[12],
+        );
+        let planes = frame.plane_data().unwrap();
+        let layouts: Vec<String> =
+            planes.iter().map(|(plane, _)| plane.layout()).collect();
+
+        assert_eq!(
+            layouts,
+            vec![
+                "PL_FMT_UNORM 8 map [0]",
+                "PL_FMT_UNORM 8+8 map [1]",
+                "PL_FMT_UNORM 24+8 map [2]",
+            ]
+        );
+        assert_eq!(planes[1].0.width(), 3);
+        assert_eq!(planes[2].0.data_size(), 24);
+        assert!(frame.from_memory(&[0u8; 24]).is_ok());
+    }
+
+    #[test]
+    fn unusable_frames_are_rejected() {
+        // I915_FORMAT_MOD_X_TILED
+        let x_tiled = 0x0100_0000_0000_0001;
+        let tiled = DrmFrame::new(DRM_FORMAT_R8, x_tiled, 4, 4, &[0], &[4]);
+        assert_eq!(
+            tiled.plane_data().err(),
+            Some(Error::UnsupportedModifier(x_tiled))
+        );
+
+        let missing = DrmFrame::new(
+            DRM_FORMAT_YUV420,
+            DRM_FORMAT_MOD_LINEAR,
+            4,
+            4,
+            &[0, 16],
+            &[4, 2],
+        );
+        assert_eq!(
+            missing.plane_data().err(),
+            Some(Error::PlaneCountMismatch {
+                expected: 3,
+                got: 2
+            })
+        );
+
+        let empty = linear(DRM_FORMAT_R8, 0, 4);
+        assert_eq!(
+            empty.plane_data().err(),
+            Some(Error::InvalidDimensions {
+                width: 0,
+                height: 4
+            })
+        );
+    }
+}
@@ -0,0 +1,169 @@
+use std::error;
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Error {
+    NoPixelData,
+    ConflictingPixelData,
+    InvalidDimensions {
+        width: usize,
+        height: usize,
+    },
+    InvalidComponentMap {
+        component: usize,
+        map: i32,
+    },
+    NoComponents,
+    PixelStrideTooSmall {
+        pixel_stride: usize,
+        min: usize,
+    },
+    RowStrideTooSmall {
+        row_stride: usize,
+        min: usize,
+    },
+    RowStrideMisaligned {
+        row_stride: usize,
+        pixel_stride: usize,
+    },
+    DataTooShort {
+        len: usize,
+        needed: usize,
+    },
+    PlaneCountMismatch {
+        expected: usize,
+        got: usize,
+    },
+    InvalidPlaneCount(usize),
+    MissingTexture {
+        plane: usize,
+    },
+    PlaneSizeMismatch {
+        plane: usize,
+        expected: (usize, usize),
+        got: (usize, usize),
+    },
+    UploadFailed,
+    TextureCreationFailed,
+    BufferCreationFailed,
+    NoTexture,
+    UnsupportedFormat(String),
+    MissingFormatCap {
+        format: String,
+        cap: String,
+    },
+    UnsupportedColorSystem(String),
+    UnsupportedPlane {
+        plane: usize,
+        layout: String,
+    },
+    UnsupportedModifier(u64),
+    DownloadFailed,
+    RenderFailed,
+    EmptyQueue,
+    InvalidDuration(f64),
+    InvalidShader(String),
+    Io(String),
+    InvalidFrame(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::NoPixelData => write!(f, "No pixels or buffer to upload"),
+            Error::ConflictingPixelData => {
+                write!(f, "Both pixels and a buffer are set")
+            }
+            Error::InvalidDimensions { width, height } => {
+                write!(f, "Invalid dimensions {}x{}", width, height)
+            }
+            Error::InvalidComponentMap { component, map } => write!(
+                f,
+                "Component {} is mapped to invalid channel {}",
+                component, map
+            ),
+            Error::NoComponents => write!(f, "The plane has no components"),
+            Error::PixelStrideTooSmall { pixel_stride, min } => write!(
+                f,
+                "Pixel stride {} is smaller than the components ({} bytes)",
+                pixel_stride, min
+            ),
+            Error::RowStrideTooSmall { row_stride, min } => write!(
+                f,
+                "Row stride {} is smaller than a row ({} bytes)",
+                row_stride, min
+            ),
+            Error::RowStrideMisaligned {
+                row_stride,
+                pixel_stride,
+            } => write!(
+                f,
+                "Row stride {} is not a multiple of the pixel stride {}",
+                row_stride, pixel_stride
+            ),
+            Error::DataTooShort { len, needed } => write!(
+                f,
+                "Pixel data is {} bytes long, {} are needed",
+                len, needed
+            ),
+            Error::PlaneCountMismatch { expected, got } => {
+                write!(f, "Expected {} planes, got {}", expected, got)
+            }
+            Error::InvalidPlaneCount(count) => {
+                write!(f, "An image needs 1 to 4 planes, got {}", count)
+            }
+            Error::MissingTexture { plane } => {
+                write!(f, "Plane {} has no texture", plane)
+            }
+            Error::PlaneSizeMismatch {
+                plane,
+                expected,
+                got,
+            } => write!(
+                f,
+                "Plane {} is {}x{}, expected {}x{}",
+                plane, got.0, got.1, expected.0, expected.1
+            ),
+            Error::UploadFailed => write!(f, "Plane upload failed"),
+            Error::TextureCreationFailed => {
+                write!(f, "Texture creation failed")
+            }
+            Error::BufferCreationFailed => write!(f, "Buffer creation failed"),
+            Error::NoTexture => write!(f, "No texture to read from"),
+            Error::UnsupportedFormat(name) => {
+                write!(f, "Unsupported texture format {}", name)
+            }
+            Error::MissingFormatCap { format, cap } => {
+                write!(f, "Texture format {} lacks {}", format, cap)
+            }
+            Error::UnsupportedColorSystem(sys) => {
+                write!(f, "Unsupported color system {}", sys)
+            }
+            Error::UnsupportedPlane { plane, layout } => write!(
+                f,
+                "Plane {} uses an unsupported layout ({})",
+                plane, layout
+            ),
+            Error::UnsupportedModifier(modifier) => {
+                write!(f, "Unsupported format modifier {:#x}", modifier)
+            }
+            Error::DownloadFailed => write!(f, "Texture download failed"),
+            Error::RenderFailed => write!(f, "Rendering failed"),
+            Error::EmptyQueue => write!(f, "No frames queued"),
+            Error::InvalidDuration(duration) => {
+                write!(f, "Invalid frame duration {}", duration)
+            }
+            Error::InvalidShader(message) => {
+                write!(f, "Invalid shader: {}", message)
+            }
+            Error::Io(message) => write!(f, "I/O error: {}", message),
+            Error::InvalidFrame(reason) => {
+                write!(f, "Invalid frame: {}", reason)
+            }
+        }
+    }
+}
+
+impl error::Error for Error {}
@@ -0,0 +1,388 @@
+use crate::colorspace::*;
+use crate::gpu::*;
+use crate::upload::*;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PixelFormat {
+    Yuv420p,
+    Yuv422p,
+    Yuv444p,
+    Yuv420p10,
+    Yuv422p10,
+    Yuv444p10,
+    Yuv420p12,
+    Yuv422p12,
+    Yuv444p12,
+    Yuv420p16,
+    Yuv422p16,
+    Yuv444p16,
+    Nv12,
+    P010,
+    Rgb8,
+    Rgba8,
+    Bgra8,
+    Rgb48,
+    Rgba64,
+    Rgba16f,
+    Rgb32f,
+    Rgba32f,
+    Gray,
+    Gray16,
+}
+
+// Layout of a single plane: the channel each component maps to, the bits
+// used to store a component and whether the plane is chroma subsampled
+struct PlaneLayout {
+    map: &'static [i32],
+    size: usize,
+    subsampled: bool,
+}
+
+const fn plane(
+    map: &'static [i32],
+    size: usize,
+    subsampled: bool,
+) -> PlaneLayout {
+    PlaneLayout {
+        map,
+        size,
+        subsampled,
+    }
+}
+
+fn lcm(a: usize, b: usize) -> usize {
+    let (mut x, mut y) = (a, b);
+    while y != 0 {
+        let r = x % y;
+        x = y;
+        y = r;
+    }
+    a / x * b
+}
+
+impl PixelFormat {
+    fn layout(&self) -> Vec<PlaneLayout> {
+        use PixelFormat::*;
+
+        match self {
+            Yuv420p | Yuv422p | Yuv444p => vec![
+                plane(&[0], 8, false),
+                plane(&[1], 8, true),
+                plane(&[2], 8, true),
+            ],
+            Yuv420p10 | Yuv422p10 | Yuv444p10 | Yuv420p12 | Yuv422p12
+            | Yuv444p12 | Yuv420p16 | Yuv422p16 | Yuv444p16 => vec![
+                plane(&[0], 16, false),
+                plane(&[1], 16, true),
+                plane(&[2], 16, true),
+            ],
+            Nv12 => vec![plane(&[0], 8, false), plane(&[1, 2], 8, true)],
+            P010 => vec![plane(&[0], 16, false), plane(&[1, 2], 16, true)],
+            Rgb8 => vec![plane(&[0, 1, 2], 8, false)],
+            Rgba8 => vec![plane(&[0, 1, 2, 3], 8, false)],
+            Bgra8 => vec![plane(&[2, 1, 0, 3], 8, false)],
+            Rgb48 => vec![plane(&[0, 1, 2], 16, false)],
+            Rgba64 => vec![plane(&[0, 1, 2, 3], 16, false)],
+            Rgba16f => vec![plane(&[0, 1, 2, 3], 16, false)],
+            Rgb32f => vec![plane(&[0, 1, 2], 32, false)],
+            Rgba32f => vec![plane(&[0, 1, 2, 3], 32, false)],
+            Gray => vec![plane(&[0], 8, false)],
+            Gray16 => vec![plane(&[0], 16, false)],
+        }
+    }
+
+    pub fn num_planes(&self) -> usize {
+        self.layout().len()
+    }
+
+    // Log2 of the horizontal and vertical chroma subsampling factors
+    pub fn chroma_shift(&self) -> (usize, usize) {
+        use PixelFormat::*;
+
+        match self {
+            Yuv420p | Yuv420p10 | Yuv420p12 | Yuv420p16 | Nv12 | P010 => (1, 1),
+            Yuv422p | Yuv422p10 | Yuv422p12 | Yuv422p16 => (1, 0),
+            _ => (0, 0),
+        }
+    }
+
+    pub fn is_yuv(&self) -> bool {
+        use PixelFormat::*;
+
+        let (sx, sy) = self.chroma_shift();
+        sx > 0
+            || sy > 0
+            || matches!(self, Yuv444p | Yuv444p10 | Yuv444p12 | Yuv444p16)
+    }
+
+    pub fn has_alpha(&self) -> bool {
+        use PixelFormat::*;
+
+        matches!(self, Rgba8 | Bgra8 | Rgba64 | Rgba16f | Rgba32f)
+    }
+
+    pub fn fmt_type(&self) -> FmtType {
+        use PixelFormat::*;
+
+        match self {
+            Rgba16f | Rgb32f | Rgba32f => FmtType::FMT_FLOAT,
+            _ => FmtType::FMT_UNORM,
+        }
+    }
+
+    // Significant bits of each component, 0 for floating point formats
+    pub fn bit_depth(&self) -> usize {
+        use PixelFormat::*;
+
+        match self {
+            Yuv420p10 | Yuv422p10 | Yuv444p10 | P010 => 10,
+            Yuv420p12 | Yuv422p12 | Yuv444p12 => 12,
+            Yuv420p16 | Yuv422p16 | Yuv444p16 | Rgb48 | Rgba64 | Gray16 => 16,
+            Rgba16f | Rgb32f | Rgba32f => 0,
+            _ => 8,
+        }
+    }
+
+    pub fn plane_size(
+        &self,
+        plane: usize,
+        width: usize,
+        height: usize,
+    ) -> (usize, usize) {
+        let (sx, sy) = self.chroma_shift();
+        match self.layout().get(plane) {
+            Some(layout) if layout.subsampled => (
+                (width + (1 << sx) - 1) >> sx,
+                (height + (1 << sy) - 1) >> sy,
+            ),
+            Some(_) => (width, height),
+            None => (0, 0),
+        }
+    }
+
+    // Channel each component of the plane maps to
+    pub fn component_map(&self, plane: usize) -> &'static [i32] {
+        self.layout().get(plane).map_or(&[], |layout| layout.map)
+    }
+
+    // Bits used to store each component of the plane
+    pub fn component_size(&self, plane: usize) -> usize {
+        self.layout().get(plane).map_or(0, |layout| layout.size)
+    }
+
+    // Position of the significant bits within a component
+    pub fn bit_shift(&self) -> usize {
+        match self {
+            PixelFormat::P010 => 6,
+            _ => 0,
+        }
+    }
+
+    pub fn pixel_stride(&self, plane: usize) -> usize {
+        self.layout()
+            .get(plane)
+            .map(|layout| layout.map.len() * layout.size / 8)
+            .unwrap_or(0)
+    }
+
+    // Row strides of a tightly packed frame
+    pub fn default_strides(&self, width: usize) -> Vec<usize> {
+        (0..self.num_planes())
+            .map(|i| self.plane_size(i, width, 0).0 * self.pixel_stride(i))
+            .collect()
+    }
+
+    // Offset and size of every plane of a frame storing its planes back to
+    // back, with the given row strides. Offsets are aligned to both the
+    // pixel stride of the plane and 4 bytes, as buffer transfers require.
+    pub fn plane_ranges(
+        &self,
+        height: usize,
+        strides: &[usize],
+    ) -> Vec<(usize, usize)> {
+        let mut end = 0;
+        strides
+            .iter()
+            .take(self.num_planes())
+            .enumerate()
+            .map(|(i, stride)| {
+                let align = lcm(self.pixel_stride(i).max(1), 4);
+                let offset = (end + align - 1) / align * align;
+                let size = stride * self.plane_size(i, 0, height).1;
+                end = offset + size;
+                (offset, size)
+            })
+            .collect()
+    }
+
+    // Bytes needed to store a frame laid out by `plane_ranges`
+    pub fn buffer_size(&self, height: usize, strides: &[usize]) -> usize {
+        self.plane_ranges(height, strides)
+            .last()
+            .map_or(0, |(offset, size)| offset + size)
+    }
+
+    // Describes every plane of a frame, the pixels or the buffer holding
+    // them are left to the caller
+    pub fn plane_data<'a>(
+        &self,
+        width: usize,
+        height: usize,
+        strides: &[usize],
+    ) -> Vec<PlaneData<'a>> {
+        let fmt_type = self.fmt_type();
+
+        self.layout()
+            .iter()
+            .enumerate()
+            .map(|(i, layout)| {
+                let (w, h) = self.plane_size(i, width, height);
+                let mut component_size = [0; 4];
+                let mut component_map = [0; 4];
+                for (c, map) in layout.map.iter().enumerate() {
+                    component_size[c] = layout.size as i32;
+                    component_map[c] = *map;
+                }
+
+                let mut data: PlaneData = Default::default();
+                data.set_type_(&fmt_type);
+                data.set_width(w);
+                data.set_height(h);
+                data.set_component_size(&component_size);
+                data.set_component_pad(&[0; 4]);
+                data.set_component_map(&component_map);
+                data.set_pixel_stride(self.pixel_stride(i));
+                data.set_row_stride(
+                    strides.get(i).copied().unwrap_or(w * self.pixel_stride(i)),
+                );
+                data
+            })
+            .collect()
+    }
+
+    pub fn default_repr(&self) -> ColorRepr {
+        let sample_depth = self.layout()[0].size;
+        let color_depth = self.bit_depth();
+        let bits = if color_depth > 0 {
+            BitEncoding::new(sample_depth, color_depth, self.bit_shift())
+        } else {
+            Default::default()
+        };
+
+        let alpha = if self.has_alpha() {
+            AlphaMode::ALPHA_INDEPENDENT
+        } else {
+            AlphaMode::ALPHA_UNKNOWN
+        };
+
+        match self {
+            PixelFormat::Gray | PixelFormat::Gray16 => ColorRepr::new(
+                &ColorSystem::COLOR_SYSTEM_BT_709,
+                &ColorLevels::COLOR_LEVELS_PC,
+                &alpha,
+                &bits,
+            ),
+            _ if self.is_yuv() => ColorRepr::new(
+                &ColorSystem::COLOR_SYSTEM_BT_709,
+                &ColorLevels::COLOR_LEVELS_TV,
+                &alpha,
+                &bits,
+            ),
+            _ => ColorRepr::new(
+                &ColorSystem::COLOR_SYSTEM_RGB,
+                &ColorLevels::COLOR_LEVELS_PC,
+                &alpha,
+                &bits,
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plane_sizes() {
+        let format = PixelFormat::Yuv420p;
+        assert_eq!(format.num_planes(), 3);
+        assert_eq!(format.plane_size(0, 5, 3), (5, 3));
+        assert_eq!(format.plane_size(1, 5, 3), (3, 2));
+        assert_eq!(format.plane_size(2, 5, 3), (3, 2));
+        assert_eq!(format.plane_size(3, 5, 3), (0, 0));
+
+        assert_eq!(PixelFormat::Yuv422p.plane_size(1, 5, 3), (3, 3));
+        assert_eq!(PixelFormat::Yuv444p.plane_size(1, 5, 3), (5, 3));
+        assert_eq!(PixelFormat::Nv12.plane_size(1, 5, 3), (3, 2));
+        assert_eq!(PixelFormat::Rgba8.plane_size(0, 5, 3), (5, 3));
+    }
+
+    #[test]
+    fn strides() {
+        assert_eq!(PixelFormat::Yuv420p.default_strides(5), vec![5, 3, 3]);
+        assert_eq!(PixelFormat::Yuv420p10.default_strides(5), vec![10, 6, 6]);
+        assert_eq!(PixelFormat::Nv12.default_strides(5), vec![5, 6]);
+        assert_eq!(PixelFormat::P010.default_strides(5), vec![10, 12]);
+        assert_eq!(PixelFormat::Rgb8.default_strides(5), vec![15]);
+        assert_eq!(PixelFormat::Rgba64.default_strides(5), vec![40]);
+        assert_eq!(PixelFormat::Rgb32f.default_strides(5), vec![60]);
+        assert_eq!(PixelFormat::Gray16.default_strides(5), vec![10]);
+    }
+
+    #[test]
+    fn layouts() {
+        assert_eq!(PixelFormat::Nv12.component_map(1), &[1, 2]);
+        assert_eq!(PixelFormat::Bgra8.component_map(0), &[2, 1, 0, 3]);
+        assert_eq!(PixelFormat::P010.component_size(1), 16);
+        assert_eq!(PixelFormat::P010.bit_depth(), 10);
+        assert_eq!(PixelFormat::P010.bit_shift(), 6);
+        assert_eq!(PixelFormat::Yuv420p12.bit_shift(), 0);
+        assert_eq!(PixelFormat::Rgba16f.bit_depth(), 0);
+
+        assert!(PixelFormat::Yuv444p.is_yuv());
+        assert!(PixelFormat::P010.is_yuv());
+        assert!(!PixelFormat::Gray.is_yuv());
+        assert!(!PixelFormat::Rgb8.is_yuv());
+        assert!(PixelFormat::Bgra8.has_alpha());
+        assert!(!PixelFormat::Rgb48.has_alpha());
+    }
+
+    #[test]
+    fn plane_ranges_are_aligned() {
+        let format = PixelFormat::Yuv420p;
+        let strides = format.default_strides(5);
+        assert_eq!(
+            format.plane_ranges(3, &strides),
+            vec![(0, 15), (16, 6), (24, 6)]
+        );
+        assert_eq!(format.buffer_size(3, &strides), 30);
+
+        let format = PixelFormat::Yuv420p10;
+        let strides = format.default_strides(5);
+        assert_eq!(
+            format.plane_ranges(3, &strides),
+            vec![(0, 30), (32, 12), (44, 12)]
+        );
+        assert_eq!(format.buffer_size(3, &strides), 56);
+
+        let format = PixelFormat::Nv12;
+        assert_eq!(format.plane_ranges(3, &[8, 8]), vec![(0, 24), (24, 16)]);
+
+        let format = PixelFormat::Rgb8;
+        assert_eq!(format.plane_ranges(3, &[15]), vec![(0, 45)]);
+        assert_eq!(format.buffer_size(3, &[]), 0);
+    }
+
+    #[test]
+    fn plane_data_describes_planes() {
+        let format = PixelFormat::Yuv420p10;
+        let strides = format.default_strides(5);
+        let planes = format.plane_data(5, 3, &strides);
+
+        assert_eq!(planes.len(), 3);
+        assert_eq!((planes[0].width(), planes[0].height()), (5, 3));
+        assert_eq!((planes[1].width(), planes[1].height()), (3, 2));
+        assert_eq!(planes[0].data_size(), 30);
+        assert_eq!(planes[1].data_size(), 12);
+    }
+}
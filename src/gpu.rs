@@ -1,10 +1,15 @@
+use crate::common::*;
+use crate::error::*;
 use crate::vulkan::*;
 use crate::*;
 
 use libplacebo_sys::*;
 
 use std::default::Default;
-use std::ptr::{null, null_mut};
+use std::ffi::{CStr, CString};
+use std::mem;
+use std::ptr::{self, null, null_mut};
+use std::slice;
 
 create_enum!(
     FmtType,
@@ -20,8 +25,23 @@ create_enum!(
     )
 );
 
-simple_enum!(
+create_enum!(
+    FmtCaps,
+    pl_fmt_caps,
+    (
+        FMT_CAP_SAMPLEABLE,
+        FMT_CAP_STORABLE,
+        FMT_CAP_LINEAR,
+        FMT_CAP_RENDERABLE,
+        FMT_CAP_BLENDABLE,
+        FMT_CAP_BLITTABLE,
+        FMT_CAP_VERTEX,
+    )
+);
+
+create_enum!(
     BufType,
+    pl_buf_type,
     (
         BUF_INVALID,
         BUF_TEX_TRANSFER,
@@ -39,7 +59,11 @@ simple_enum!(
     (HANDLE_FD, HANDLE_WIN32, HANDLE_WIN32_KMT, HANDLE_DMA_BUF)
 );
 
-simple_enum!(BufMemType, (BUF_MEM_AUTO, BUF_MEM_HOST, BUF_MEM_DEVICE));
+create_enum!(
+    BufMemType,
+    pl_buf_mem_type,
+    (BUF_MEM_AUTO, BUF_MEM_HOST, BUF_MEM_DEVICE)
+);
 
 pub union Handle {
     handle: pl_handle,
@@ -91,11 +115,124 @@ impl Gpu {
         }
     }
 
+    pub fn find_fmt(
+        &self,
+        type_: &FmtType,
+        num_components: usize,
+        min_depth: usize,
+        host_bits: usize,
+        caps: &FmtCaps,
+    ) -> Option<Fmt> {
+        let fmt = unsafe {
+            pl_find_fmt(
+                self.gpu,
+                type_.to_pl_fmt_type(),
+                num_components as i32,
+                min_depth as i32,
+                host_bits as i32,
+                caps.to_pl_fmt_caps(),
+            )
+        };
+        Fmt::from_ptr(fmt)
+    }
+
+    pub fn find_named_fmt(&self, name: &str) -> Option<Fmt> {
+        let name = CString::new(name).ok()?;
+        let fmt = unsafe { pl_find_named_fmt(self.gpu, name.as_ptr()) };
+        Fmt::from_ptr(fmt)
+    }
+
     pub(crate) fn get_ptr(&self) -> *const pl_gpu {
         self.gpu
     }
 }
 
+#[derive(Clone, Copy)]
+pub struct Fmt {
+    fmt: *const pl_fmt,
+}
+
+impl Fmt {
+    pub(crate) fn from_ptr(fmt: *const pl_fmt) -> Option<Self> {
+        if fmt.is_null() {
+            None
+        } else {
+            Some(Fmt { fmt })
+        }
+    }
+
+    pub fn name(&self) -> String {
+        unsafe { CStr::from_ptr((*self.fmt).name) }
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    pub fn is_float(&self) -> bool {
+        unsafe { (*self.fmt).type_ == pl_fmt_type::PL_FMT_FLOAT }
+    }
+
+    pub fn is_unorm(&self) -> bool {
+        unsafe { (*self.fmt).type_ == pl_fmt_type::PL_FMT_UNORM }
+    }
+
+    pub fn num_components(&self) -> usize {
+        unsafe { (*self.fmt).num_components as usize }
+    }
+
+    pub fn component_depth(&self) -> [usize; 4] {
+        let depth = unsafe { (*self.fmt).component_depth };
+        [
+            depth[0] as usize,
+            depth[1] as usize,
+            depth[2] as usize,
+            depth[3] as usize,
+        ]
+    }
+
+    pub fn host_bits(&self) -> [usize; 4] {
+        let bits = unsafe { (*self.fmt).host_bits };
+        [
+            bits[0] as usize,
+            bits[1] as usize,
+            bits[2] as usize,
+            bits[3] as usize,
+        ]
+    }
+
+    pub fn texel_size(&self) -> usize {
+        unsafe { (*self.fmt).texel_size }
+    }
+
+    // `caps` holds a mask of `pl_fmt_caps` bits, so it is read as an
+    // integer rather than as the enum
+    pub fn has_cap(&self, cap: &FmtCaps) -> bool {
+        let caps =
+            unsafe { ptr::read(&(*self.fmt).caps as *const _ as *const u32) };
+        caps & cap.to_pl_fmt_caps() as u32 != 0
+    }
+
+    // Opaque formats have no host representation and can't be transferred
+    pub fn is_opaque(&self) -> bool {
+        unsafe { (*self.fmt).opaque }
+    }
+
+    // Component each sample of a texel holds, in memory order, e.g.
+    // [2, 1, 0, 3] for bgra8
+    pub fn sample_order(&self) -> [usize; 4] {
+        let order = unsafe { (*self.fmt).sample_order };
+        [
+            order[0] as usize,
+            order[1] as usize,
+            order[2] as usize,
+            order[3] as usize,
+        ]
+    }
+
+    pub(crate) fn get_ptr(&self) -> *const pl_fmt {
+        self.fmt
+    }
+}
+
 set_struct!(BufParams, buf_params, pl_buf_params);
 
 impl Default for BufParams {
@@ -121,6 +258,28 @@ impl Default for BufParams {
     }
 }
 
+set_params!(
+    BufParams,
+    buf_params,
+    (
+        type_,
+        size,
+        host_mapped,
+        host_writable,
+        host_readable,
+        memory_type
+    ),
+    (&BufType, usize, bool, bool, bool, &BufMemType),
+    (
+        type_.to_pl_buf_type(),
+        size,
+        host_mapped,
+        host_writable,
+        host_readable,
+        memory_type.to_pl_buf_mem_type(),
+    )
+);
+
 pub struct Buf {
     buf: *const pl_buf,
     gpu: *const pl_gpu,
@@ -133,6 +292,86 @@ impl Buf {
         Buf { buf, gpu: gpu.gpu }
     }
 
+    pub fn try_new(gpu: &Gpu, params: &BufParams) -> Result<Self> {
+        let buf = unsafe { pl_buf_create(gpu.gpu, &params.buf_params) };
+        if buf.is_null() {
+            return Err(Error::BufferCreationFailed);
+        }
+
+        Ok(Buf { buf, gpu: gpu.gpu })
+    }
+
+    pub fn size(&self) -> usize {
+        unsafe { (*self.buf).params.size }
+    }
+
+    pub fn write(&self, offset: usize, data: &[u8]) -> Result<()> {
+        let needed = offset + data.len();
+        if needed > self.size() {
+            return Err(Error::DataTooShort {
+                len: self.size(),
+                needed,
+            });
+        }
+
+        unsafe {
+            pl_buf_write(
+                self.gpu,
+                self.buf,
+                offset,
+                data.as_ptr() as *const _,
+                data.len(),
+            );
+        }
+        Ok(())
+    }
+
+    pub fn read(&self, offset: usize, data: &mut [u8]) -> Result<()> {
+        let needed = offset + data.len();
+        if needed > self.size() {
+            return Err(Error::DataTooShort {
+                len: self.size(),
+                needed,
+            });
+        }
+
+        let ok = unsafe {
+            pl_buf_read(
+                self.gpu,
+                self.buf,
+                offset,
+                data.as_mut_ptr() as *mut _,
+                data.len(),
+            )
+        };
+
+        if ok {
+            Ok(())
+        } else {
+            Err(Error::DownloadFailed)
+        }
+    }
+
+    // Memory of a `host_mapped` buffer, only valid to access while the GPU
+    // does not use the buffer
+    pub fn data(&self) -> Option<&[u8]> {
+        let ptr = self.mapped_ptr();
+        if ptr.is_null() {
+            return None;
+        }
+        Some(unsafe { slice::from_raw_parts(ptr, self.size()) })
+    }
+
+    pub(crate) fn mapped_ptr(&self) -> *mut u8 {
+        unsafe { (*self.buf).data }
+    }
+
+    // Returns true while the GPU still uses the buffer, waiting at most
+    // `timeout` nanoseconds for it to become idle
+    pub fn poll(&self, timeout: u64) -> bool {
+        unsafe { pl_buf_poll(self.gpu, self.buf, timeout) }
+    }
+
     pub(crate) fn get_ptr(&self) -> *const pl_buf {
         self.buf
     }
@@ -147,6 +386,84 @@ impl Drop for Buf {
 }
 
 set_struct!(TexParams, tex_params, pl_tex_params);
+
+set_params!(
+    TexParams,
+    tex_params,
+    (
+        w,
+        h,
+        d,
+        format,
+        sampleable,
+        renderable,
+        storable,
+        blit_src,
+        blit_dst,
+        host_writable,
+        host_readable,
+    ),
+    (
+        usize,
+        usize,
+        usize,
+        &Fmt,
+        bool,
+        bool,
+        bool,
+        bool,
+        bool,
+        bool,
+        bool,
+    ),
+    (
+        w as i32,
+        h as i32,
+        d as i32,
+        format.get_ptr(),
+        sampleable as bool,
+        renderable as bool,
+        storable as bool,
+        blit_src as bool,
+        blit_dst as bool,
+        host_writable as bool,
+        host_readable as bool,
+    )
+);
+
+impl TexParams {
+    pub fn w(&self) -> usize {
+        self.tex_params.w as usize
+    }
+
+    pub fn h(&self) -> usize {
+        self.tex_params.h as usize
+    }
+
+    pub fn d(&self) -> usize {
+        self.tex_params.d as usize
+    }
+
+    pub fn sampleable(&self) -> bool {
+        self.tex_params.sampleable
+    }
+
+    pub fn renderable(&self) -> bool {
+        self.tex_params.renderable
+    }
+
+    pub fn host_writable(&self) -> bool {
+        self.tex_params.host_writable
+    }
+
+    pub fn host_readable(&self) -> bool {
+        self.tex_params.host_readable
+    }
+
+    pub(crate) fn from_pl(tex_params: pl_tex_params) -> Self {
+        TexParams { tex_params }
+    }
+}
 impl Default for TexParams {
     fn default() -> Self {
         let shared_mem: SharedMem = Default::default();
@@ -203,6 +520,210 @@ impl Tex {
         Tex { tex, gpu: gpu.gpu }
     }
 
+    pub fn try_new(gpu: &Gpu, params: &TexParams) -> Result<Self> {
+        let tex = unsafe { pl_tex_create(gpu.gpu, &params.tex_params) };
+        if tex.is_null() {
+            return Err(Error::TextureCreationFailed);
+        }
+
+        Ok(Tex { tex, gpu: gpu.gpu })
+    }
+
+    pub fn params(&self) -> Option<TexParams> {
+        if self.tex.is_null() {
+            return None;
+        }
+        Some(TexParams::from_pl(unsafe { (*self.tex).params }))
+    }
+
+    pub fn format(&self) -> Option<Fmt> {
+        if self.tex.is_null() {
+            return None;
+        }
+        Fmt::from_ptr(unsafe { (*self.tex).params.format })
+    }
+
+    // Reads back the whole texture, rows are tightly packed
+    pub fn download(&self, data: &mut [u8]) -> Result<()> {
+        let (params, fmt) = match (self.params(), self.format()) {
+            (Some(params), Some(fmt)) => (params, fmt),
+            _ => return Err(Error::NoTexture),
+        };
+
+        let needed = params.w() * params.h().max(1) * fmt.texel_size();
+        if data.len() < needed {
+            return Err(Error::DataTooShort {
+                len: data.len(),
+                needed,
+            });
+        }
+
+        let ok = unsafe {
+            let mut transfer: pl_tex_transfer_params = mem::zeroed();
+            transfer.tex = self.tex;
+            transfer.ptr = data.as_mut_ptr() as *mut _;
+            pl_tex_download(self.gpu, &transfer)
+        };
+
+        if ok {
+            Ok(())
+        } else {
+            Err(Error::DownloadFailed)
+        }
+    }
+
+    // Uploads `data` into the given region of the texture, `stride` being
+    // the length of a row of `data` in texels
+    pub fn upload_region(
+        &self,
+        rect: &Rect2D,
+        stride: usize,
+        data: &[u8],
+    ) -> Result<()> {
+        let (params, fmt) = match (self.params(), self.format()) {
+            (Some(params), Some(fmt)) => (params, fmt),
+            _ => return Err(Error::NoTexture),
+        };
+
+        let (w, h) = (rect.width(), rect.height());
+        if rect.x1() > params.w() || rect.y1() > params.h().max(1) {
+            return Err(Error::InvalidDimensions {
+                width: rect.x1(),
+                height: rect.y1(),
+            });
+        }
+        if w == 0 || h == 0 {
+            return Ok(());
+        }
+
+        let texel_size = fmt.texel_size();
+        let needed = ((h - 1) * stride + w) * texel_size;
+        if data.len() < needed {
+            return Err(Error::DataTooShort {
+                len: data.len(),
+                needed,
+            });
+        }
+
+        let ok = unsafe {
+            let mut transfer: pl_tex_transfer_params = mem::zeroed();
+            transfer.tex = self.tex;
+            transfer.rc = pl_rect3d {
+                x0: rect.x0() as i32,
+                y0: rect.y0() as i32,
+                z0: 0,
+                x1: rect.x1() as i32,
+                y1: rect.y1() as i32,
+                z1: 1,
+            };
+            transfer.stride_w = stride as i32;
+            transfer.ptr = data.as_ptr() as *mut _;
+            pl_tex_upload(self.gpu, &transfer)
+        };
+
+        if ok {
+            Ok(())
+        } else {
+            Err(Error::UploadFailed)
+        }
+    }
+
+    // Queues a download of the whole texture into `buf` without waiting for
+    // it, the data can be read once `Buf::poll` reports the buffer as idle
+    pub fn download_to_buf(&self, buf: &Buf, offset: usize) -> Result<()> {
+        let (params, fmt) = match (self.params(), self.format()) {
+            (Some(params), Some(fmt)) => (params, fmt),
+            _ => return Err(Error::NoTexture),
+        };
+
+        let needed = offset + params.w() * params.h().max(1) * fmt.texel_size();
+        if buf.size() < needed {
+            return Err(Error::DataTooShort {
+                len: buf.size(),
+                needed,
+            });
+        }
+
+        let ok = unsafe {
+            let mut transfer: pl_tex_transfer_params = mem::zeroed();
+            transfer.tex = self.tex;
+            transfer.buf = buf.get_ptr();
+            transfer.buf_offset = offset;
+            pl_tex_download(self.gpu, &transfer)
+        };
+
+        if ok {
+            Ok(())
+        } else {
+            Err(Error::DownloadFailed)
+        }
+    }
+
+    pub fn download_rgba8(&self) -> Result<Vec<u8>> {
+        self.download_with(|v| (v * 255.0).round() as u8, u8::MAX)
+    }
+
+    pub fn download_rgba16(&self) -> Result<Vec<u16>> {
+        self.download_with(|v| (v * 65535.0).round() as u16, u16::MAX)
+    }
+
+    pub fn download_f32(&self) -> Result<Vec<f32>> {
+        self.download_with(|v| v, 1.0)
+    }
+
+    // Downloads the texture and expands every texel to four components in
+    // RGBA order, missing ones are zero apart from alpha
+    fn download_with<T, F>(&self, convert: F, opaque: T) -> Result<Vec<T>>
+    where
+        T: Copy + Default,
+        F: Fn(f32) -> T,
+    {
+        let fmt = self.format().ok_or(Error::NoTexture)?;
+        let texel_size = fmt.texel_size();
+        let num_components = fmt.num_components();
+        let bits = fmt.host_bits();
+        let order = fmt.sample_order();
+
+        let supported = (fmt.is_unorm() || fmt.is_float())
+            && bits[..num_components]
+                .iter()
+                .all(|b| *b == bits[0] && (*b == 8 || *b == 16 || *b == 32))
+            && (fmt.is_float() || bits[0] != 32)
+            && bits[0] / 8 * num_components == texel_size
+            && order[..num_components].iter().all(|c| *c < 4);
+        if !supported {
+            return Err(Error::UnsupportedFormat(fmt.name()));
+        }
+
+        let texels = self.params().map_or(0, |p| p.w() * p.h());
+        let mut raw = vec![0; texels * texel_size];
+        self.download(&mut raw)?;
+
+        let size = bits[0] / 8;
+        let float = fmt.is_float();
+        let decode = |b: &[u8]| -> f32 {
+            match (size, float) {
+                (1, _) => f32::from(b[0]) / 255.0,
+                (2, false) => {
+                    f32::from(u16::from_ne_bytes([b[0], b[1]])) / 65535.0
+                }
+                (2, true) => f16_to_f32(u16::from_ne_bytes([b[0], b[1]])),
+                _ => f32::from_ne_bytes([b[0], b[1], b[2], b[3]]),
+            }
+        };
+
+        let mut out = Vec::with_capacity(raw.len() / texel_size * 4);
+        for texel in raw.chunks_exact(texel_size) {
+            let mut rgba = [T::default(), T::default(), T::default(), opaque];
+            for (c, value) in texel.chunks_exact(size).enumerate() {
+                rgba[order[c]] = convert(decode(value));
+            }
+            out.extend_from_slice(&rgba);
+        }
+
+        Ok(out)
+    }
+
     /*
      TODO We need to test these ones because surely they hide some memory errors
 
@@ -259,3 +780,16 @@ impl Drop for Tex {
         }
     }
 }
+
+fn f16_to_f32(h: u16) -> f32 {
+    let sign = if h & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exp = i32::from((h >> 10) & 0x1f);
+    let mant = f32::from(h & 0x3ff);
+
+    match exp {
+        0 => sign * mant * 2f32.powi(-24),
+        31 if mant == 0.0 => sign * f32::INFINITY,
+        31 => f32::NAN,
+        _ => sign * (1.0 + mant / 1024.0) * 2f32.powi(exp - 15),
+    }
+}
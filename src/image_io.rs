@@ -0,0 +1,130 @@
+use crate::colorspace::*;
+use crate::error::*;
+use crate::format::*;
+use crate::gpu::*;
+use crate::renderer::*;
+use crate::sample::*;
+
+use image::{
+    DynamicImage, ImageBuffer, Luma, Pixel, Rgb, Rgb32FImage, Rgba,
+    Rgba32FImage, RgbaImage,
+};
+
+use std::ops::Deref;
+
+pub trait ImagePixel: Pixel {
+    const FORMAT: PixelFormat;
+}
+
+macro_rules! image_pixel {
+    ($pixel:ty, $format:ident) => {
+        impl ImagePixel for $pixel {
+            const FORMAT: PixelFormat = PixelFormat::$format;
+        }
+    };
+}
+
+image_pixel!(Luma<u8>, Gray);
+image_pixel!(Luma<u16>, Gray16);
+image_pixel!(Rgb<u8>, Rgb8);
+image_pixel!(Rgba<u8>, Rgba8);
+image_pixel!(Rgb<u16>, Rgb48);
+image_pixel!(Rgba<u16>, Rgba64);
+image_pixel!(Rgb<f32>, Rgb32f);
+image_pixel!(Rgba<f32>, Rgba32f);
+
+impl OwnedImage {
+    pub fn from_image_buffer<P, C>(
+        gpu: &Gpu,
+        buffer: &ImageBuffer<P, C>,
+    ) -> Result<Self>
+    where
+        P: ImagePixel,
+        P::Subpixel: Sample,
+        C: Deref<Target = [P::Subpixel]>,
+    {
+        let (width, height) = buffer.dimensions();
+        let samples: &[P::Subpixel] = buffer;
+
+        Image::upload(
+            gpu,
+            P::FORMAT,
+            &[as_bytes(samples)],
+            width as usize,
+            height as usize,
+            &P::FORMAT.default_repr(),
+            &ColorSpace::color_space(&ColorSpaces::Srgb),
+        )
+    }
+
+    pub fn from_dynamic_image(gpu: &Gpu, image: &DynamicImage) -> Result<Self> {
+        match image {
+            DynamicImage::ImageLuma8(buffer) => {
+                Self::from_image_buffer(gpu, buffer)
+            }
+            DynamicImage::ImageLuma16(buffer) => {
+                Self::from_image_buffer(gpu, buffer)
+            }
+            DynamicImage::ImageRgb8(buffer) => {
+                Self::from_image_buffer(gpu, buffer)
+            }
+            DynamicImage::ImageRgba8(buffer) => {
+                Self::from_image_buffer(gpu, buffer)
+            }
+            DynamicImage::ImageRgb16(buffer) => {
+                Self::from_image_buffer(gpu, buffer)
+            }
+            DynamicImage::ImageRgba16(buffer) => {
+                Self::from_image_buffer(gpu, buffer)
+            }
+            DynamicImage::ImageRgb32F(buffer) => {
+                Self::from_image_buffer(gpu, buffer)
+            }
+            DynamicImage::ImageRgba32F(buffer) => {
+                Self::from_image_buffer(gpu, buffer)
+            }
+            DynamicImage::ImageLumaA16(_) => {
+                Self::from_image_buffer(gpu, &image.to_rgba16())
+            }
+            _ => Self::from_image_buffer(gpu, &image.to_rgba8()),
+        }
+    }
+}
+
+impl RenderTarget {
+    fn dimensions(&self) -> Result<(u32, u32)> {
+        let params = self
+            .texture()
+            .and_then(|tex| tex.params())
+            .ok_or(Error::NoTexture)?;
+        Ok((params.w() as u32, params.h() as u32))
+    }
+
+    pub fn to_rgba8_image(&self) -> Result<RgbaImage> {
+        let (width, height) = self.dimensions()?;
+        let data = self.download_rgba8()?;
+        ImageBuffer::from_raw(width, height, data).ok_or(Error::DownloadFailed)
+    }
+
+    pub fn to_rgba16_image(&self) -> Result<ImageBuffer<Rgba<u16>, Vec<u16>>> {
+        let (width, height) = self.dimensions()?;
+        let data = self.download_rgba16()?;
+        ImageBuffer::from_raw(width, height, data).ok_or(Error::DownloadFailed)
+    }
+
+    pub fn to_rgba32f_image(&self) -> Result<Rgba32FImage> {
+        let (width, height) = self.dimensions()?;
+        let data = self.download_f32()?;
+        ImageBuffer::from_raw(width, height, data).ok_or(Error::DownloadFailed)
+    }
+
+    pub fn to_rgb32f_image(&self) -> Result<Rgb32FImage> {
+        let (width, height) = self.dimensions()?;
+        let data = self
+            .download_f32()?
+            .chunks_exact(4)
+            .flat_map(|texel| texel[..3].iter().copied())
+            .collect();
+        ImageBuffer::from_raw(width, height, data).ok_or(Error::DownloadFailed)
+    }
+}
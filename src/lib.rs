@@ -1,15 +1,29 @@
 #![allow(non_camel_case_types)]
 #![allow(non_snake_case)]
 
+#[cfg(feature = "av-data")]
+pub mod av;
+pub mod batch;
 pub mod colorspace;
 pub mod common;
 pub mod context;
+pub mod drm;
+pub mod error;
 pub mod filter;
+pub mod format;
 pub mod gpu;
+#[cfg(feature = "image")]
+pub mod image_io;
+pub mod overlay;
+pub mod planar;
+pub mod queue;
 pub mod renderer;
+pub mod sample;
 pub mod shaders;
 pub mod swapchain;
 pub mod upload;
+pub mod viewport;
 pub mod vulkan;
+pub mod y4m;
 
 mod utility;
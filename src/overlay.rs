@@ -0,0 +1,481 @@
+use crate::colorspace::*;
+use crate::common::*;
+use crate::error::*;
+use crate::gpu::*;
+use crate::renderer::*;
+use crate::upload::*;
+
+use std::rc::Rc;
+
+// An overlay owning its texture, added with `add_overlay` to an `Image` to
+// be placed in image coordinates, or to a `RenderTarget` to be placed in
+// target coordinates. Both keep the texture alive while they refer to it.
+#[derive(Clone)]
+pub struct OwnedOverlay {
+    overlay: Overlay,
+    tex: Rc<Tex>,
+}
+
+impl OwnedOverlay {
+    pub fn overlay(&self) -> &Overlay {
+        &self.overlay
+    }
+
+    pub(crate) fn texture(&self) -> Rc<Tex> {
+        self.tex.clone()
+    }
+}
+
+fn rgb_repr(alpha: &AlphaMode) -> ColorRepr {
+    ColorRepr::new(
+        &ColorSystem::COLOR_SYSTEM_RGB,
+        &ColorLevels::COLOR_LEVELS_PC,
+        alpha,
+        &Default::default(),
+    )
+}
+
+// Uploads a bitmap with 8 bit components and tightly packed rows
+fn upload_bitmap(
+    gpu: &Gpu,
+    data: &[u8],
+    width: usize,
+    height: usize,
+    components: usize,
+) -> Result<(Tex, Plane)> {
+    if width == 0 || height == 0 {
+        return Err(Error::InvalidDimensions { width, height });
+    }
+
+    let mut component_size = [0; 4];
+    let mut component_map = [0; 4];
+    for c in 0..components {
+        component_size[c] = 8;
+        component_map[c] = c as i32;
+    }
+
+    let mut plane_data: PlaneData = Default::default();
+    plane_data.set_type_(&FmtType::FMT_UNORM);
+    plane_data.set_width(width);
+    plane_data.set_height(height);
+    plane_data.set_component_size(&component_size);
+    plane_data.set_component_map(&component_map);
+    plane_data.set_pixel_stride(components);
+    plane_data.set_row_stride(width * components);
+    plane_data.set_pixels(data);
+
+    let mut tex = Tex::default(gpu);
+    let mut plane: Plane = Default::default();
+    plane_data.upload_plane(gpu, &mut plane, &mut tex)?;
+
+    Ok((tex, plane))
+}
+
+pub struct OverlayBuilder {
+    tex: Tex,
+    plane: Plane,
+    mode: OverlayMode,
+    rect: Rect2D,
+    base_color: [f32; 3],
+    repr: ColorRepr,
+    color: ColorSpace,
+}
+
+impl OverlayBuilder {
+    // Straight alpha RGBA with 8 bits per component and tightly packed rows,
+    // in sRGB unless changed with `set_color`
+    pub fn from_rgba(
+        gpu: &Gpu,
+        data: &[u8],
+        width: usize,
+        height: usize,
+    ) -> Result<Self> {
+        OverlayBuilder::upload(gpu, data, width, height, 4)
+    }
+
+    // One 8 bit coverage value per pixel, e.g. a rendered glyph, drawn in
+    // the base color
+    pub fn from_alpha_mask(
+        gpu: &Gpu,
+        data: &[u8],
+        width: usize,
+        height: usize,
+    ) -> Result<Self> {
+        let mut builder = OverlayBuilder::upload(gpu, data, width, height, 1)?;
+        builder.mode = OverlayMode::OVERLAY_MONOCHROME;
+        Ok(builder)
+    }
+
+    fn upload(
+        gpu: &Gpu,
+        data: &[u8],
+        width: usize,
+        height: usize,
+        components: usize,
+    ) -> Result<Self> {
+        let (tex, plane) = upload_bitmap(gpu, data, width, height, components)?;
+
+        Ok(OverlayBuilder {
+            tex,
+            plane,
+            mode: OverlayMode::OVERLAY_NORMAL,
+            rect: Rect2D::new(0, 0, width, height),
+            base_color: [1.0, 1.0, 1.0],
+            repr: rgb_repr(&AlphaMode::ALPHA_INDEPENDENT),
+            color: ColorSpace::color_space(&ColorSpaces::Srgb),
+        })
+    }
+
+    // Moves the top left corner, keeping the size
+    pub fn set_position(&mut self, x: usize, y: usize) {
+        let (w, h) = (self.rect.width(), self.rect.height());
+        self.rect = Rect2D::new(x, y, x + w, y + h);
+    }
+
+    // Scales the bitmap to the given size, keeping the position
+    pub fn set_size(&mut self, width: usize, height: usize) {
+        let (x, y) = (self.rect.x0(), self.rect.y0());
+        self.rect = Rect2D::new(x, y, x + width, y + height);
+    }
+
+    pub fn set_rect(&mut self, rect: &Rect2D) {
+        self.rect = rect.clone();
+    }
+
+    // Color of alpha masks, ignored for RGBA bitmaps
+    pub fn set_base_color(&mut self, base_color: &[f32; 3]) {
+        self.base_color = *base_color;
+    }
+
+    pub fn set_repr(&mut self, repr: &ColorRepr) {
+        self.repr = repr.clone();
+    }
+
+    pub fn set_color(&mut self, color: &ColorSpace) {
+        self.color = color.clone();
+    }
+
+    pub fn build(self) -> OwnedOverlay {
+        OwnedOverlay {
+            overlay: Overlay::new(
+                &self.plane,
+                &self.rect,
+                &self.mode,
+                &self.base_color,
+                &self.repr,
+                &self.color,
+            ),
+            tex: Rc::new(self.tex),
+        }
+    }
+}
+
+// Textures are rounded up to a multiple of this size, so bitmaps of similar
+// size can reuse them in later frames
+const POOL_GRANULARITY: usize = 32;
+
+fn pool_size(len: usize) -> usize {
+    (len + POOL_GRANULARITY - 1) / POOL_GRANULARITY * POOL_GRANULARITY
+}
+
+// A pooled texture and the bitmap it last received, tightly packed from its
+// top left corner. The rest of the texture is transparent.
+struct PoolTexture {
+    tex: Rc<Tex>,
+    plane: Plane,
+    components: usize,
+    size: (usize, usize),
+    extent: (usize, usize),
+    texels: Vec<u8>,
+}
+
+impl PoolTexture {
+    fn holds(
+        &self,
+        components: usize,
+        extent: (usize, usize),
+        texels: &[u8],
+    ) -> bool {
+        self.components == components
+            && self.extent == extent
+            && self.texels == texels
+    }
+
+    fn fits(&self, components: usize, (w, h): (usize, usize)) -> bool {
+        self.components == components && self.size.0 >= w && self.size.1 >= h
+    }
+
+    // Uploads the region covering both the previous and the new bitmap,
+    // clearing whatever the new one doesn't cover
+    fn replace(
+        &mut self,
+        extent: (usize, usize),
+        texels: Vec<u8>,
+    ) -> Result<()> {
+        let (w, h) = extent;
+        let uw = w.max(self.extent.0);
+        let uh = h.max(self.extent.1);
+        let c = self.components;
+
+        let mut region = vec![0; uw * uh * c];
+        for (y, row) in texels.chunks_exact(w * c).enumerate() {
+            region[y * uw * c..][..w * c].copy_from_slice(row);
+        }
+        self.tex
+            .upload_region(&Rect2D::new(0, 0, uw, uh), uw, &region)?;
+
+        self.extent = extent;
+        self.texels = texels;
+
+        Ok(())
+    }
+}
+
+// Draws the many small bitmaps a subtitle renderer outputs for every frame,
+// each as its own overlay with its own rect and color. libplacebo always
+// samples the whole texture of an overlay, so bitmaps can't share a packed
+// texture. Instead they get textures from a pool kept across frames, and
+// only bitmaps that differ from what a texture already holds are uploaded.
+//
+// Textures are reused by the next frame, so the overlays of a frame show its
+// bitmaps until `begin_frame` is called again.
+pub struct OverlayPool {
+    color: ColorSpace,
+    textures: Vec<PoolTexture>,
+    in_use: Vec<bool>,
+    overlays: Vec<OwnedOverlay>,
+}
+
+impl Default for OverlayPool {
+    fn default() -> Self {
+        OverlayPool {
+            color: ColorSpace::color_space(&ColorSpaces::Srgb),
+            textures: Vec::new(),
+            in_use: Vec::new(),
+            overlays: Vec::new(),
+        }
+    }
+}
+
+impl OverlayPool {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    // Color space the bitmap colors are given in
+    pub fn set_color(&mut self, color: &ColorSpace) {
+        self.color = color.clone();
+    }
+
+    // Returns every texture to the pool and drops the overlays of the
+    // previous frame
+    pub fn begin_frame(&mut self) {
+        for in_use in &mut self.in_use {
+            *in_use = false;
+        }
+        self.overlays.clear();
+    }
+
+    // Adds an 8 bit coverage mask placed at `rect`, drawn in `color` with
+    // straight alpha. `stride` is the length of a row of `data` in bytes.
+    pub fn add_alpha(
+        &mut self,
+        gpu: &Gpu,
+        data: &[u8],
+        stride: usize,
+        rect: &Rect2D,
+        color: &[f32; 4],
+    ) -> Result<()> {
+        // The mask is the alpha of monochrome overlays, so the alpha of the
+        // color is applied to it beforehand
+        let alpha = color[3].max(0.0).min(1.0);
+        let texels = match pack_rows(data, stride, rect, 1)? {
+            Some(texels) if alpha < 1.0 => texels
+                .iter()
+                .map(|v| (f32::from(*v) * alpha).round() as u8)
+                .collect(),
+            Some(texels) => texels,
+            None => return Ok(()),
+        };
+
+        self.add(
+            gpu,
+            texels,
+            1,
+            rect,
+            &OverlayMode::OVERLAY_MONOCHROME,
+            &[color[0], color[1], color[2]],
+        )
+    }
+
+    // Adds straight alpha RGBA with 8 bits per component placed at `rect`
+    pub fn add_rgba(
+        &mut self,
+        gpu: &Gpu,
+        data: &[u8],
+        stride: usize,
+        rect: &Rect2D,
+    ) -> Result<()> {
+        let texels = match pack_rows(data, stride, rect, 4)? {
+            Some(texels) => texels,
+            None => return Ok(()),
+        };
+
+        self.add(
+            gpu,
+            texels,
+            4,
+            rect,
+            &OverlayMode::OVERLAY_NORMAL,
+            &[1.0, 1.0, 1.0],
+        )
+    }
+
+    fn add(
+        &mut self,
+        gpu: &Gpu,
+        texels: Vec<u8>,
+        components: usize,
+        rect: &Rect2D,
+        mode: &OverlayMode,
+        base_color: &[f32; 3],
+    ) -> Result<()> {
+        let extent = (rect.width(), rect.height());
+        let i = self.texture_for(gpu, components, extent, texels)?;
+        self.in_use[i] = true;
+
+        // Drawn unscaled, the transparent rest of the texture extends the
+        // rect to the right and bottom
+        let texture = &self.textures[i];
+        let (x, y) = (rect.x0(), rect.y0());
+        let overlay = Overlay::new(
+            &texture.plane,
+            &Rect2D::new(x, y, x + texture.size.0, y + texture.size.1),
+            mode,
+            base_color,
+            &rgb_repr(&AlphaMode::ALPHA_INDEPENDENT),
+            &self.color,
+        );
+        self.overlays.push(OwnedOverlay {
+            overlay,
+            tex: texture.tex.clone(),
+        });
+
+        Ok(())
+    }
+
+    // A free texture holding the bitmap, or else the smallest free one it
+    // fits in, or else a new one
+    fn texture_for(
+        &mut self,
+        gpu: &Gpu,
+        components: usize,
+        extent: (usize, usize),
+        texels: Vec<u8>,
+    ) -> Result<usize> {
+        let free = |i: &usize| !self.in_use[*i];
+        let indices = 0..self.textures.len();
+
+        if let Some(i) = indices
+            .clone()
+            .filter(free)
+            .find(|i| self.textures[*i].holds(components, extent, &texels))
+        {
+            return Ok(i);
+        }
+
+        let fit = indices
+            .filter(free)
+            .filter(|i| self.textures[*i].fits(components, extent))
+            .min_by_key(|i| {
+                self.textures[*i].size.0 * self.textures[*i].size.1
+            });
+        if let Some(i) = fit {
+            self.textures[i].replace(extent, texels)?;
+            return Ok(i);
+        }
+
+        let size = (pool_size(extent.0), pool_size(extent.1));
+        let blank = vec![0; size.0 * size.1 * components];
+        let (tex, plane) =
+            upload_bitmap(gpu, &blank, size.0, size.1, components)?;
+        let mut texture = PoolTexture {
+            tex: Rc::new(tex),
+            plane,
+            components,
+            size,
+            extent: (0, 0),
+            texels: Vec::new(),
+        };
+        texture.replace(extent, texels)?;
+
+        self.textures.push(texture);
+        self.in_use.push(false);
+
+        Ok(self.textures.len() - 1)
+    }
+
+    // The overlays of the bitmaps added since `begin_frame`, for
+    // `add_overlay`
+    pub fn overlays(&self) -> Vec<OwnedOverlay> {
+        self.overlays.clone()
+    }
+}
+
+// Copies the rows of a bitmap placed at `rect` into a tightly packed buffer,
+// None when the bitmap is empty
+fn pack_rows(
+    data: &[u8],
+    stride: usize,
+    rect: &Rect2D,
+    texel_size: usize,
+) -> Result<Option<Vec<u8>>> {
+    let (w, h) = (rect.width(), rect.height());
+    if w == 0 || h == 0 {
+        return Ok(None);
+    }
+
+    let row_size = w * texel_size;
+    let needed = (h - 1) * stride + row_size;
+    if data.len() < needed {
+        return Err(Error::DataTooShort {
+            len: data.len(),
+            needed,
+        });
+    }
+
+    let mut texels = Vec::with_capacity(row_size * h);
+    for y in 0..h {
+        texels.extend_from_slice(&data[y * stride..][..row_size]);
+    }
+
+    Ok(Some(texels))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pool_sizes_round_up() {
+        assert_eq!(pool_size(1), 32);
+        assert_eq!(pool_size(32), 32);
+        assert_eq!(pool_size(33), 64);
+    }
+
+    #[test]
+    fn rows_are_packed() {
+        let data = [1, 2, 0, 3, 4, 0, 5, 6];
+        let rect = Rect2D::new(10, 20, 12, 23);
+
+        assert_eq!(
+            pack_rows(&data, 3, &rect, 1),
+            Ok(Some(vec![1, 2, 3, 4, 5, 6]))
+        );
+        assert_eq!(pack_rows(&data, 3, &Rect2D::new(4, 4, 4, 8), 1), Ok(None));
+        assert_eq!(
+            pack_rows(&data[..7], 3, &rect, 1),
+            Err(Error::DataTooShort { len: 7, needed: 8 })
+        );
+    }
+}
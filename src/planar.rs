@@ -0,0 +1,273 @@
+use crate::colorspace::*;
+use crate::error::*;
+use crate::format::*;
+use crate::gpu::*;
+use crate::renderer::*;
+
+use libplacebo_sys::*;
+
+// Renders into the planes of a YCbCr or gray `PixelFormat`. libplacebo
+// renders into a single texture, so one full resolution pass produces every
+// component and the chroma planes are filtered down to the chroma location
+// of the target when read back, left sited unless set otherwise.
+pub struct PlanarTarget {
+    format: PixelFormat,
+    width: usize,
+    height: usize,
+    chroma_location: ChromaLocation,
+    target: RenderTarget,
+}
+
+// Position of a chroma sample along one axis of the block it covers
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Siting {
+    Start,
+    Center,
+    End,
+}
+
+// Horizontal and vertical siting, an unknown location is left sited like
+// libplacebo does
+fn siting(location: &ChromaLocation) -> (Siting, Siting) {
+    use ChromaLocation::*;
+
+    match location {
+        CHROMA_CENTER => (Siting::Center, Siting::Center),
+        CHROMA_TOP_LEFT => (Siting::Start, Siting::Start),
+        CHROMA_TOP_CENTER => (Siting::Center, Siting::Start),
+        CHROMA_BOTTOM_LEFT => (Siting::Start, Siting::End),
+        CHROMA_BOTTOM_CENTER => (Siting::Center, Siting::End),
+        _ => (Siting::Start, Siting::Center),
+    }
+}
+
+// Offsets from the start of a block of `1 << shift` samples and their
+// weights. Center sited samples average the block, cosited ones use a
+// triangle filter around the sample they share with luma
+fn chroma_taps(shift: usize, siting: Siting) -> Vec<(isize, u32)> {
+    let n = 1isize << shift;
+    let tent = |center: isize| {
+        (1 - n..n)
+            .map(move |i| (center + i, (n - i.abs()) as u32))
+            .collect()
+    };
+
+    match siting {
+        Siting::Start => tent(0),
+        Siting::Center => (0..n).map(|i| (i, 1)).collect(),
+        Siting::End => tent(n - 1),
+    }
+}
+
+impl PlanarTarget {
+    // `repr` has to be a YCbCr system, its bit encoding is replaced by the
+    // one of the 16 bit texture rendered to
+    pub fn new(
+        gpu: &Gpu,
+        format: PixelFormat,
+        width: usize,
+        height: usize,
+        repr: &ColorRepr,
+        color: &ColorSpace,
+    ) -> Result<Self> {
+        let gray = matches!(format, PixelFormat::Gray | PixelFormat::Gray16);
+        if !format.is_yuv() && !gray {
+            return Err(Error::UnsupportedFormat(format!("{:?}", format)));
+        }
+        if width == 0 || height == 0 {
+            return Err(Error::InvalidDimensions { width, height });
+        }
+
+        let sys = repr.internal_object().sys;
+        if !unsafe { pl_color_system_is_ycbcr_like(sys) } {
+            return Err(Error::UnsupportedColorSystem(format!("{:?}", sys)));
+        }
+
+        let fmt = gpu
+            .find_fmt(
+                &FmtType::FMT_UNORM,
+                4,
+                16,
+                16,
+                &FmtCaps::FMT_CAP_RENDERABLE,
+            )
+            .ok_or_else(|| Error::UnsupportedFormat("rgba16".to_owned()))?;
+
+        let mut repr = repr.clone();
+        repr.set_bits(&BitEncoding::new(16, 16, 0));
+        let target =
+            RenderTarget::offscreen(gpu, width, height, &fmt, &repr, color)?;
+
+        Ok(PlanarTarget {
+            format,
+            width,
+            height,
+            chroma_location: ChromaLocation::CHROMA_LEFT,
+            target,
+        })
+    }
+
+    pub fn chroma_location(&self) -> ChromaLocation {
+        self.chroma_location
+    }
+
+    pub fn set_chroma_location(&mut self, location: &ChromaLocation) {
+        self.chroma_location = *location;
+    }
+
+    pub fn format(&self) -> PixelFormat {
+        self.format
+    }
+
+    pub fn size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    pub fn target(&self) -> &RenderTarget {
+        &self.target
+    }
+
+    pub fn render(
+        &self,
+        renderer: &Renderer,
+        image: &Image,
+        params: &RenderParams,
+    ) -> Result<RenderReport> {
+        renderer.render_image(image, &self.target, params)
+    }
+
+    // Reads the planes back in the layout `PixelFormat` uses for uploads,
+    // with tightly packed rows
+    pub fn download(&self) -> Result<Vec<Vec<u8>>> {
+        let strides = self.format.default_strides(self.width);
+        let mut planes: Vec<Vec<u8>> = (0..self.format.num_planes())
+            .map(|i| {
+                let (_, h) = self.format.plane_size(i, self.width, self.height);
+                vec![0; strides[i] * h]
+            })
+            .collect();
+
+        {
+            let mut data: Vec<&mut [u8]> =
+                planes.iter_mut().map(|p| p.as_mut_slice()).collect();
+            self.download_into(&mut data, &strides)?;
+        }
+
+        Ok(planes)
+    }
+
+    pub fn download_into(
+        &self,
+        planes: &mut [&mut [u8]],
+        strides: &[usize],
+    ) -> Result<()> {
+        let num_planes = self.format.num_planes();
+        if planes.len() != num_planes || strides.len() != num_planes {
+            return Err(Error::PlaneCountMismatch {
+                expected: num_planes,
+                got: planes.len().min(strides.len()),
+            });
+        }
+
+        let texels = self.target.download_rgba16()?;
+
+        let depth = self.format.bit_depth();
+        let shift = self.format.bit_shift();
+        let max = (1u32 << depth) - 1;
+        let (h_siting, v_siting) = siting(&self.chroma_location);
+        let clamp =
+            |v: isize, len: usize| v.max(0).min(len as isize - 1) as usize;
+
+        for (plane, (data, stride)) in
+            planes.iter_mut().zip(strides.iter()).enumerate()
+        {
+            let map = self.format.component_map(plane);
+            let size = self.format.component_size(plane) / 8;
+            let (w, h) = self.format.plane_size(plane, self.width, self.height);
+            let (sx, sy) = if plane > 0 {
+                self.format.chroma_shift()
+            } else {
+                (0, 0)
+            };
+
+            let row_size = w * map.len() * size;
+            let needed = (h - 1) * stride + row_size;
+            if *stride < row_size || data.len() < needed {
+                return Err(Error::DataTooShort {
+                    len: data.len(),
+                    needed,
+                });
+            }
+
+            let x_taps = chroma_taps(sx, h_siting);
+            let y_taps = chroma_taps(sy, v_siting);
+
+            for (y, row) in data.chunks_mut(*stride).take(h).enumerate() {
+                let mut out = row.iter_mut();
+                for x in 0..w {
+                    for c in map {
+                        // Weighted sum around the sample, repeating edges
+                        let mut sum = 0;
+                        let mut count = 0;
+                        for (dy, wy) in &y_taps {
+                            let ry =
+                                clamp((y << sy) as isize + dy, self.height);
+                            let line = ry * self.width;
+                            for (dx, wx) in &x_taps {
+                                let rx =
+                                    clamp((x << sx) as isize + dx, self.width);
+                                let texel =
+                                    texels[(line + rx) * 4 + *c as usize];
+                                sum += wy * wx * u32::from(texel);
+                                count += wy * wx;
+                            }
+                        }
+                        let v = (sum + count / 2) / count;
+
+                        let code = ((v * max + 32767) / 65535) << shift;
+                        if size == 1 {
+                            *out.next().unwrap() = code as u8;
+                        } else {
+                            for b in &(code as u16).to_ne_bytes() {
+                                *out.next().unwrap() = *b;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chroma_taps_by_siting() {
+        assert_eq!(chroma_taps(0, Siting::Start), vec![(0, 1)]);
+        assert_eq!(chroma_taps(0, Siting::Center), vec![(0, 1)]);
+        assert_eq!(chroma_taps(1, Siting::Center), vec![(0, 1), (1, 1)]);
+        assert_eq!(
+            chroma_taps(1, Siting::Start),
+            vec![(-1, 1), (0, 2), (1, 1)]
+        );
+        assert_eq!(chroma_taps(1, Siting::End), vec![(0, 1), (1, 2), (2, 1)]);
+    }
+
+    #[test]
+    fn siting_of_chroma_locations() {
+        use ChromaLocation::*;
+
+        assert_eq!(siting(&CHROMA_LEFT), (Siting::Start, Siting::Center));
+        assert_eq!(siting(&CHROMA_UNKNOWN), (Siting::Start, Siting::Center));
+        assert_eq!(siting(&CHROMA_CENTER), (Siting::Center, Siting::Center));
+        assert_eq!(siting(&CHROMA_TOP_LEFT), (Siting::Start, Siting::Start));
+        assert_eq!(
+            siting(&CHROMA_BOTTOM_CENTER),
+            (Siting::Center, Siting::End)
+        );
+    }
+}
@@ -0,0 +1,132 @@
+use crate::error::*;
+use crate::renderer::*;
+
+use std::collections::VecDeque;
+
+struct QueuedFrame {
+    image: OwnedImage,
+    pts: f64,
+    duration: f64,
+    signature: u64,
+}
+
+// Frames waiting to be displayed, rendered by mixing the frames around each
+// vsync with the `frame_mixer` of the `RenderParams`. Timestamps are in
+// seconds.
+pub struct FrameQueue {
+    frames: VecDeque<QueuedFrame>,
+    next_signature: u64,
+    retention: f64,
+}
+
+impl Default for FrameQueue {
+    fn default() -> Self {
+        FrameQueue {
+            frames: VecDeque::new(),
+            next_signature: 1,
+            retention: 2.0,
+        }
+    }
+}
+
+impl FrameQueue {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    // Number of frame durations a frame is kept around after it ended, it
+    // should cover the radius of the frame mixer
+    pub fn set_retention(&mut self, frames: f64) {
+        self.retention = frames;
+    }
+
+    // Queues a frame and returns the signature identifying it, frames have
+    // to be pushed in presentation order
+    pub fn push(
+        &mut self,
+        mut image: OwnedImage,
+        pts: f64,
+        duration: f64,
+    ) -> u64 {
+        let signature = self.next_signature;
+        self.next_signature += 1;
+        image.image_mut().set_signature(signature as usize);
+
+        self.frames.push_back(QueuedFrame {
+            image,
+            pts,
+            duration,
+            signature,
+        });
+
+        signature
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+
+    // Timestamp of the last queued frame's end, to tell when to queue more
+    pub fn end_pts(&self) -> Option<f64> {
+        self.frames.back().map(|frame| frame.pts + frame.duration)
+    }
+
+    // Drops the frames too old to contribute to a vsync at `pts`
+    pub fn drop_before(&mut self, pts: f64) {
+        while self.frames.len() > 1 {
+            let frame = &self.frames[0];
+            if frame.pts + frame.duration * (1.0 + self.retention) >= pts {
+                break;
+            }
+            self.frames.pop_front();
+        }
+    }
+
+    pub fn render(
+        &mut self,
+        renderer: &Renderer,
+        target: &RenderTarget,
+        params: &RenderParams,
+        vsync_pts: f64,
+        vsync_duration: f64,
+    ) -> Result<RenderReport> {
+        self.drop_before(vsync_pts);
+
+        // The mixer works in units of source frames, use the duration of
+        // the frame shown at the vsync
+        let current = self
+            .frames
+            .iter()
+            .rev()
+            .find(|frame| frame.pts <= vsync_pts)
+            .or_else(|| self.frames.front())
+            .ok_or(Error::EmptyQueue)?;
+        let unit = if current.duration > 0.0 {
+            current.duration
+        } else {
+            vsync_duration
+        };
+        if !unit.is_finite() || unit <= 0.0 {
+            return Err(Error::InvalidDuration(unit));
+        }
+
+        let mut mix = FrameMix::new((vsync_duration / unit) as f32);
+        for frame in &self.frames {
+            mix.add_frame(
+                frame.image.image(),
+                frame.signature,
+                ((frame.pts - vsync_pts) / unit) as f32,
+            );
+        }
+
+        renderer.render_image_mix(&mix, target, params)
+    }
+}
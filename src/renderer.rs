@@ -1,16 +1,27 @@
 use crate::colorspace::*;
 use crate::common::*;
 use crate::context::*;
+use crate::error::*;
 use crate::filter::*;
+use crate::format::*;
 use crate::gpu::*;
+use crate::overlay::*;
+use crate::sample::*;
 use crate::shaders::colorspace::*;
+use crate::shaders::custom::*;
 use crate::shaders::sampling::*;
 use crate::swapchain::*;
+use crate::upload::*;
 use crate::*;
 
 use libplacebo_sys::*;
 
-use std::ptr::null;
+use std::cell::RefCell;
+use std::fmt;
+use std::marker::PhantomData;
+use std::mem;
+use std::ptr::{null, null_mut};
+use std::rc::Rc;
 
 create_enum!(
     OverlayMode,
@@ -22,9 +33,21 @@ macro_rules! overlays {
     ($id:ident) => {
         pub fn set_overlays(&mut self, overlays: &[Overlay]) {
             self.overlays = overlays.iter().map(|v| v.overlay).collect();
+            self.overlay_texs.clear();
             self.$id.overlays = self.overlays.as_ptr();
             self.$id.num_overlays = overlays.len() as i32;
         }
+
+        pub fn add_overlay(&mut self, overlay: &OwnedOverlay) {
+            self.overlays.push(overlay.overlay().overlay);
+            self.overlay_texs.push(overlay.texture());
+            self.$id.overlays = self.overlays.as_ptr();
+            self.$id.num_overlays = self.overlays.len() as i32;
+        }
+
+        pub fn clear_overlays(&mut self) {
+            self.set_overlays(&[]);
+        }
     }
 }
 
@@ -52,6 +75,47 @@ impl Plane {
     pub fn height(&self) -> usize {
         unsafe { (*self.plane.texture).params.h as usize }
     }
+
+    pub fn has_texture(&self) -> bool {
+        !self.plane.texture.is_null()
+    }
+
+    pub fn texture_params(&self) -> Option<TexParams> {
+        if self.plane.texture.is_null() {
+            return None;
+        }
+        Some(TexParams::from_pl(unsafe { (*self.plane.texture).params }))
+    }
+
+    pub fn components(&self) -> usize {
+        self.plane.components as usize
+    }
+
+    pub fn component_mapping(&self) -> [i32; 4] {
+        self.plane.component_mapping
+    }
+
+    pub fn shift_x(&self) -> f32 {
+        self.plane.shift_x
+    }
+
+    pub fn shift_y(&self) -> f32 {
+        self.plane.shift_y
+    }
+
+    // Planes carrying only the chroma channels of a YCbCr image
+    fn is_chroma(&self) -> bool {
+        let n = self.components().min(4);
+        n > 0
+            && self.plane.component_mapping[..n]
+                .iter()
+                .all(|c| *c == 1 || *c == 2)
+    }
+
+    pub(crate) fn from_pl(plane: pl_plane) -> Self {
+        Plane { plane }
+    }
+
     pub(crate) fn get_mut_ptr(&mut self) -> *mut pl_plane {
         &mut self.plane
     }
@@ -80,9 +144,48 @@ create_struct!(
     )
 );
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Rotation {
+    Rotate0,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+}
+
+impl Rotation {
+    pub fn is_quarter_turn(&self) -> bool {
+        matches!(self, Rotation::Rotate90 | Rotation::Rotate270)
+    }
+}
+
+// Clockwise rotations by a quarter turn, transposing the image before it
+// gets scaled
+const ROTATE_90_SHADER: &str = "//!HOOK MAIN
+//!BIND HOOKED
+//!WIDTH HOOKED.h
+//!HEIGHT HOOKED.w
+//!DESC rotate 90
+vec4 hook() {
+    return HOOKED_tex(vec2(HOOKED_pos.y, 1.0 - HOOKED_pos.x));
+}
+";
+
+const ROTATE_270_SHADER: &str = "//!HOOK MAIN
+//!BIND HOOKED
+//!WIDTH HOOKED.h
+//!HEIGHT HOOKED.w
+//!DESC rotate 270
+vec4 hook() {
+    return HOOKED_tex(vec2(1.0 - HOOKED_pos.y, HOOKED_pos.x));
+}
+";
+
 pub struct Image {
     img: pl_image,
     overlays: Vec<pl_overlay>,
+    overlay_texs: Vec<Rc<Tex>>,
+    rotation: Rotation,
+    mirror: bool,
 }
 
 impl Default for Image {
@@ -111,6 +214,9 @@ impl Default for Image {
         Image {
             img,
             overlays: Vec::new(),
+            overlay_texs: Vec::new(),
+            rotation: Rotation::Rotate0,
+            mirror: false,
         }
     }
 }
@@ -118,9 +224,8 @@ impl Default for Image {
 set_params!(
     Image,
     img,
-    (signature, num_planes, repr, color, profile, width, height, src_rect),
+    (signature, repr, color, profile, width, height, src_rect),
     (
-        usize,
         usize,
         &ColorRepr,
         &ColorSpace,
@@ -131,7 +236,6 @@ set_params!(
     ),
     (
         signature as u64,
-        num_planes as i32,
         repr.internal_object(),
         color.internal_object(),
         profile.internal_object(),
@@ -150,22 +254,365 @@ impl Image {
         ColorSpace::from_pl(self.img.color)
     }
 
-    pub fn set_planes(&mut self, planes: &[&Plane; 4]) {
-        let pl_planes: [pl_plane; 4] = [
-            planes[0].clone().plane,
-            planes[1].clone().plane,
-            planes[2].clone().plane,
-            planes[3].clone().plane,
-        ];
-        self.img.planes = pl_planes;
+    pub fn src_rect(&self) -> Rect2DF {
+        let rect = self.img.src_rect;
+        Rect2DF::new(rect.x0, rect.y0, rect.x1, rect.y1)
+    }
+
+    // Applied by the renderer, quarter turns swap the width and height the
+    // image is displayed with
+    pub fn set_rotation(&mut self, rotation: Rotation) {
+        self.rotation = rotation;
+    }
+
+    pub fn rotation(&self) -> Rotation {
+        self.rotation
+    }
+
+    // Flips the displayed image horizontally, after rotating it. Overlays
+    // attached to the image are placed by libplacebo and are not mirrored
+    pub fn set_mirror(&mut self, mirror: bool) {
+        self.mirror = mirror;
+    }
+
+    pub fn mirror(&self) -> bool {
+        self.mirror
+    }
+
+    pub fn display_size(&self) -> (usize, usize) {
+        if self.rotation.is_quarter_turn() {
+            (self.height(), self.width())
+        } else {
+            (self.width(), self.height())
+        }
+    }
+
+    // The image as passed to libplacebo, with half turns and mirroring turned
+    // into flipped source rects. Quarter turns are left to the renderer's
+    // transpose hook, which keeps the rect orientation, so the flips still
+    // land in display space.
+    pub(crate) fn to_pl(&self) -> pl_image {
+        let mut img = self.img;
+        let rect = &mut img.src_rect;
+        if rect.x0 == rect.x1 || rect.y0 == rect.y1 {
+            *rect = pl_rect2df {
+                x0: 0.0,
+                y0: 0.0,
+                x1: img.width as f32,
+                y1: img.height as f32,
+            };
+        }
+
+        if self.mirror != (self.rotation == Rotation::Rotate180) {
+            mem::swap(&mut rect.x0, &mut rect.x1);
+        }
+        if self.rotation == Rotation::Rotate180 {
+            mem::swap(&mut rect.y0, &mut rect.y1);
+        }
+
+        img
+    }
+
+    pub fn set_chroma_location(&mut self, chroma_loc: &ChromaLocation) {
+        unsafe {
+            pl_image_set_chroma_location(
+                &mut self.img,
+                chroma_loc.to_pl_chroma_location(),
+            );
+        }
+    }
+
+    pub fn set_planes(&mut self, planes: &[Plane]) -> Result<()> {
+        if planes.is_empty() || planes.len() > 4 {
+            return Err(Error::InvalidPlaneCount(planes.len()));
+        }
+        for (i, plane) in planes.iter().enumerate() {
+            self.img.planes[i] = plane.plane;
+        }
+        let empty: Plane = Default::default();
+        for i in planes.len()..4 {
+            self.img.planes[i] = empty.plane;
+        }
+        self.img.num_planes = planes.len() as i32;
+        Ok(())
+    }
+
+    pub fn num_planes(&self) -> usize {
+        self.img.num_planes as usize
+    }
+
+    pub fn plane(&self, index: usize) -> Option<Plane> {
+        if index < self.num_planes() {
+            Some(Plane::from_pl(self.img.planes[index]))
+        } else {
+            None
+        }
+    }
+
+    pub fn planes(&self) -> Vec<Plane> {
+        (0..self.num_planes())
+            .map(|i| Plane::from_pl(self.img.planes[i]))
+            .collect()
+    }
+
+    pub fn width(&self) -> usize {
+        self.img.width as usize
+    }
+
+    pub fn height(&self) -> usize {
+        self.img.height as usize
+    }
+
+    // Checks the planes against the image size, chroma planes are expected to
+    // be subsampled by the given log2 factors
+    pub fn validate(&self, chroma_shift: (usize, usize)) -> Result<()> {
+        let num_planes = self.num_planes();
+        if num_planes == 0 || num_planes > 4 {
+            return Err(Error::InvalidPlaneCount(num_planes));
+        }
+
+        let (width, height) = (self.width(), self.height());
+        if width == 0 || height == 0 {
+            return Err(Error::InvalidDimensions { width, height });
+        }
+
+        let (sx, sy) = chroma_shift;
+        for (i, plane) in self.planes().iter().enumerate() {
+            if !plane.has_texture() {
+                return Err(Error::MissingTexture { plane: i });
+            }
+
+            for (c, map) in plane
+                .component_mapping()
+                .iter()
+                .take(plane.components().min(4))
+                .enumerate()
+            {
+                if !(0..=3).contains(map) {
+                    return Err(Error::InvalidComponentMap {
+                        component: c,
+                        map: *map,
+                    });
+                }
+            }
+
+            let expected = if plane.is_chroma() {
+                (
+                    (width + (1 << sx) - 1) >> sx,
+                    (height + (1 << sy) - 1) >> sy,
+                )
+            } else {
+                (width, height)
+            };
+            let got = (plane.width(), plane.height());
+            if got != expected {
+                return Err(Error::PlaneSizeMismatch {
+                    plane: i,
+                    expected,
+                    got,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    // Checks that the GPU can upload every plane of `format`, so callers can
+    // fall back to converting the frame on the CPU
+    pub fn check_format(gpu: &Gpu, format: PixelFormat) -> Result<()> {
+        let strides = format.default_strides(1);
+        for (plane, data) in
+            format.plane_data(1, 1, &strides).iter().enumerate()
+        {
+            if data.find_fmt(gpu).is_none() {
+                return Err(Error::UnsupportedPlane {
+                    plane,
+                    layout: data.layout(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn upload(
+        gpu: &Gpu,
+        format: PixelFormat,
+        data: &[&[u8]],
+        width: usize,
+        height: usize,
+        repr: &ColorRepr,
+        color: &ColorSpace,
+    ) -> Result<OwnedImage> {
+        let mut image = OwnedImage::new(gpu, format);
+        image.upload(data, width, height, repr, color)?;
+        Ok(image)
     }
 
     overlays!(img);
 }
 
+// An image together with the textures backing its planes, the textures are
+// reused by the following uploads as long as the frame size is unchanged
+pub struct OwnedImage {
+    gpu: Gpu,
+    format: PixelFormat,
+    image: Image,
+    planes: Vec<Plane>,
+    textures: Vec<Tex>,
+}
+
+impl OwnedImage {
+    pub fn new(gpu: &Gpu, format: PixelFormat) -> Self {
+        OwnedImage {
+            gpu: gpu.clone(),
+            format,
+            image: Default::default(),
+            planes: Vec::new(),
+            textures: Vec::new(),
+        }
+    }
+
+    pub fn upload(
+        &mut self,
+        data: &[&[u8]],
+        width: usize,
+        height: usize,
+        repr: &ColorRepr,
+        color: &ColorSpace,
+    ) -> Result<()> {
+        let strides = self.format.default_strides(width);
+        self.upload_with_strides(data, &strides, width, height, repr, color)
+    }
+
+    // Uploads typed samples, `strides` are in bytes
+    pub fn upload_samples<T: Sample>(
+        &mut self,
+        data: &[&[T]],
+        strides: &[usize],
+        width: usize,
+        height: usize,
+        repr: &ColorRepr,
+        color: &ColorSpace,
+    ) -> Result<()> {
+        let sample_fits = (0..self.format.num_planes())
+            .all(|i| self.format.component_size(i) == T::BITS);
+        if !sample_fits
+            || self.format.fmt_type().to_pl_fmt_type()
+                != T::FMT_TYPE.to_pl_fmt_type()
+        {
+            return Err(Error::UnsupportedFormat(format!(
+                "{:?} from {}-bit {:?} samples",
+                self.format,
+                T::BITS,
+                T::FMT_TYPE
+            )));
+        }
+
+        let data: Vec<&[u8]> = data.iter().map(|d| as_bytes(d)).collect();
+        self.upload_with_strides(&data, strides, width, height, repr, color)
+    }
+
+    pub fn upload_with_strides(
+        &mut self,
+        data: &[&[u8]],
+        strides: &[usize],
+        width: usize,
+        height: usize,
+        repr: &ColorRepr,
+        color: &ColorSpace,
+    ) -> Result<()> {
+        let num_planes = self.format.num_planes();
+        if data.len() != num_planes {
+            return Err(Error::PlaneCountMismatch {
+                expected: num_planes,
+                got: data.len(),
+            });
+        }
+
+        let mut plane_data = self.format.plane_data(width, height, strides);
+        for (plane, pixels) in plane_data.iter_mut().zip(data) {
+            plane.set_pixels(pixels);
+        }
+
+        self.upload_planes(&plane_data, width, height, repr, color)
+    }
+
+    // Uploads planes described by the caller, e.g. backed by a `Buf`
+    pub fn upload_planes(
+        &mut self,
+        plane_data: &[PlaneData],
+        width: usize,
+        height: usize,
+        repr: &ColorRepr,
+        color: &ColorSpace,
+    ) -> Result<()> {
+        let num_planes = self.format.num_planes();
+        if plane_data.len() != num_planes {
+            return Err(Error::PlaneCountMismatch {
+                expected: num_planes,
+                got: plane_data.len(),
+            });
+        }
+        for (i, plane) in plane_data.iter().enumerate() {
+            plane.validate()?;
+            if plane.find_fmt(&self.gpu).is_none() {
+                return Err(Error::UnsupportedPlane {
+                    plane: i,
+                    layout: plane.layout(),
+                });
+            }
+        }
+
+        let gpu = &self.gpu;
+        self.textures.resize_with(num_planes, || Tex::default(gpu));
+        self.planes.resize_with(num_planes, Default::default);
+
+        for ((data, plane), tex) in plane_data
+            .iter()
+            .zip(self.planes.iter_mut())
+            .zip(self.textures.iter_mut())
+        {
+            data.upload_plane(&self.gpu, plane, tex)?;
+        }
+
+        self.image.set_planes(&self.planes)?;
+        self.image.set_width(width);
+        self.image.set_height(height);
+        self.image.set_repr(repr);
+        self.image.set_color(color);
+
+        self.image.validate(self.format.chroma_shift())
+    }
+
+    pub fn format(&self) -> PixelFormat {
+        self.format
+    }
+
+    pub fn image(&self) -> &Image {
+        &self.image
+    }
+
+    pub fn image_mut(&mut self) -> &mut Image {
+        &mut self.image
+    }
+}
+
+// libplacebo flips the rect of flipped swapchain frames itself,
+// `RenderTarget::to_pl` does so instead to combine it with mirroring
+fn unflip_rect(rect: &mut pl_rect2d) {
+    if rect.y0 > rect.y1 {
+        mem::swap(&mut rect.y0, &mut rect.y1);
+    }
+}
+
 pub struct RenderTarget {
     target: pl_render_target,
     overlays: Vec<pl_overlay>,
+    overlay_texs: Vec<Rc<Tex>>,
+    tex: Option<Tex>,
+    flipped: bool,
+    mirror: bool,
 }
 
 impl Default for RenderTarget {
@@ -187,6 +634,10 @@ impl Default for RenderTarget {
         RenderTarget {
             target,
             overlays: Vec::new(),
+            overlay_texs: Vec::new(),
+            tex: None,
+            flipped: false,
+            mirror: false,
         }
     }
 }
@@ -206,18 +657,213 @@ set_params!(
 );
 
 impl RenderTarget {
+    // A target rendering into a texture it owns, which can be read back.
+    // It can only be cleared when the format is blittable
+    pub fn offscreen(
+        gpu: &Gpu,
+        width: usize,
+        height: usize,
+        format: &Fmt,
+        repr: &ColorRepr,
+        color: &ColorSpace,
+    ) -> Result<Self> {
+        if !format.has_cap(&FmtCaps::FMT_CAP_RENDERABLE) {
+            return Err(Error::MissingFormatCap {
+                format: format.name(),
+                cap: format!("{:?}", FmtCaps::FMT_CAP_RENDERABLE),
+            });
+        }
+        if format.is_opaque() {
+            return Err(Error::MissingFormatCap {
+                format: format.name(),
+                cap: "host readback".to_string(),
+            });
+        }
+
+        let mut params: TexParams = Default::default();
+        params.set_w(width);
+        params.set_h(height);
+        params.set_format(format);
+        params.set_renderable(true);
+        params.set_sampleable(true);
+        params.set_blit_dst(format.has_cap(&FmtCaps::FMT_CAP_BLITTABLE));
+        params.set_host_readable(true);
+        let tex = Tex::try_new(gpu, &params)?;
+
+        let mut target: RenderTarget = Default::default();
+        target.set_fbo(&tex);
+        target.set_dst_rect(&Rect2D::new(0, 0, width, height));
+        target.set_repr(repr);
+        target.set_color(color);
+        target.tex = Some(tex);
+
+        Ok(target)
+    }
+
     pub fn render_target_from_swapchain(&mut self, frame: &SwapchainFrame) {
         unsafe {
             pl_render_target_from_swapchain(&mut self.target, frame.get_ptr());
+            self.flipped = (*frame.get_ptr()).flipped;
+        }
+
+        unflip_rect(&mut self.target.dst_rect);
+    }
+
+    // Whether the target is stored upside down, as some swapchains are
+    pub fn set_flipped(&mut self, flipped: bool) {
+        self.flipped = flipped;
+    }
+
+    pub fn flipped(&self) -> bool {
+        self.flipped
+    }
+
+    // Flips everything rendered to the target horizontally
+    pub fn set_mirror(&mut self, mirror: bool) {
+        self.mirror = mirror;
+    }
+
+    pub fn mirror(&self) -> bool {
+        self.mirror
+    }
+
+    pub fn dst_rect(&self) -> Rect2D {
+        let rect = self.target.dst_rect;
+        Rect2D::new(
+            rect.x0.min(rect.x1) as usize,
+            rect.y0.min(rect.y1) as usize,
+            rect.x0.max(rect.x1) as usize,
+            rect.y0.max(rect.y1) as usize,
+        )
+    }
+
+    // The target as passed to libplacebo, the dst rect flipped as needed on
+    // top of any flip the rect was given with
+    pub(crate) fn to_pl(&self) -> pl_render_target {
+        let mut target = self.target;
+        let rect = &mut target.dst_rect;
+        if self.flipped {
+            mem::swap(&mut rect.y0, &mut rect.y1);
         }
+        if self.mirror {
+            mem::swap(&mut rect.x0, &mut rect.x1);
+        }
+
+        target
+    }
+
+    pub fn texture(&self) -> Option<&Tex> {
+        self.tex.as_ref()
+    }
+
+    // Size of the texture rendered to
+    pub fn size(&self) -> Option<(usize, usize)> {
+        if self.target.fbo.is_null() {
+            return None;
+        }
+        let params = unsafe { (*self.target.fbo).params };
+        Some((params.w as usize, params.h as usize))
+    }
+
+    // Fills the whole target with `color`
+    pub fn clear(&self, gpu: &Gpu, color: &[f32; 4]) -> Result<()> {
+        if self.target.fbo.is_null() {
+            return Err(Error::NoTexture);
+        }
+        let params = unsafe { (*self.target.fbo).params };
+        if !params.blit_dst {
+            return Err(Error::MissingFormatCap {
+                format: Fmt::from_ptr(params.format)
+                    .map_or_else(String::new, |f| f.name()),
+                cap: format!("{:?}", FmtCaps::FMT_CAP_BLITTABLE),
+            });
+        }
+        unsafe {
+            pl_tex_clear(gpu.get_ptr(), self.target.fbo, color.as_ptr());
+        }
+        Ok(())
+    }
+
+    pub fn download_rgba8(&self) -> Result<Vec<u8>> {
+        self.tex.as_ref().ok_or(Error::NoTexture)?.download_rgba8()
+    }
+
+    pub fn download_rgba16(&self) -> Result<Vec<u16>> {
+        self.tex.as_ref().ok_or(Error::NoTexture)?.download_rgba16()
+    }
+
+    pub fn download_f32(&self) -> Result<Vec<f32>> {
+        self.tex.as_ref().ok_or(Error::NoTexture)?.download_f32()
     }
 
     overlays!(target);
 }
 
-set_struct!(RenderParams, params, pl_render_params);
+pub enum RenderProfiles {
+    Default,
+    HighQuality,
+}
+
+#[derive(Clone)]
+pub struct RenderParams {
+    params: pl_render_params,
+    hooks: Vec<Hook>,
+    hook_ptrs: Vec<*const pl_hook>,
+}
+
+impl Default for RenderParams {
+    fn default() -> Self {
+        RenderParams::render_params(&RenderProfiles::Default)
+    }
+}
+
+impl RenderParams {
+    pub fn render_params(profile: &RenderProfiles) -> Self {
+        let params = unsafe {
+            match profile {
+                RenderProfiles::Default => pl_render_default_params,
+                RenderProfiles::HighQuality => pl_render_high_quality_params,
+            }
+        };
+        RenderParams {
+            params,
+            hooks: Vec::new(),
+            hook_ptrs: Vec::new(),
+        }
+    }
+
+    // Hooks run in the order they are added
+    pub fn add_hook(&mut self, hook: &Hook) {
+        self.hook_ptrs.push(hook.get_ptr());
+        self.hooks.push(hook.clone());
+    }
 
-default_struct!(RenderParams, params, unsafe { pl_render_default_params });
+    pub fn add_user_shader(&mut self, shader: UserShader) {
+        self.add_hook(&shader.into_hook());
+    }
+
+    // Runs the hook ahead of every hook already added
+    pub(crate) fn prepend_hook(&mut self, hook: &Hook) {
+        self.hook_ptrs.insert(0, hook.get_ptr());
+        self.hooks.insert(0, hook.clone());
+    }
+
+    pub fn clear_hooks(&mut self) {
+        self.hooks.clear();
+        self.hook_ptrs.clear();
+    }
+
+    pub fn hooks(&self) -> &[Hook] {
+        &self.hooks
+    }
+
+    pub(crate) fn to_pl(&self) -> pl_render_params {
+        let mut params = self.params;
+        params.hooks = self.hook_ptrs.as_ptr();
+        params.num_hooks = self.hook_ptrs.len() as i32;
+        params
+    }
+}
 
 set_params!(
     RenderParams,
@@ -293,17 +939,322 @@ set_params!(
     )
 );
 
-pub struct Renderer {
+// Frames surrounding a vsync, blended by the `frame_mixer` of the
+// `RenderParams`. Timestamps are relative to the vsync being rendered, in
+// units of source frames.
+pub struct FrameMix<'a> {
+    frames: Vec<pl_image>,
+    signatures: Vec<u64>,
+    timestamps: Vec<f32>,
+    vsync_duration: f32,
+    rotation: Rotation,
+    _images: PhantomData<&'a Image>,
+}
+
+impl<'a> FrameMix<'a> {
+    pub fn new(vsync_duration: f32) -> Self {
+        FrameMix {
+            frames: Vec::new(),
+            signatures: Vec::new(),
+            timestamps: Vec::new(),
+            vsync_duration,
+            rotation: Rotation::Rotate0,
+            _images: PhantomData,
+        }
+    }
+
+    // Frames have to be added in increasing timestamp order, the signature
+    // identifies a frame across calls for redraw caching. The rotation of the
+    // first frame applies to the whole mix.
+    pub fn add_frame(&mut self, image: &'a Image, signature: u64, ts: f32) {
+        if self.frames.is_empty() {
+            self.rotation = image.rotation();
+        }
+        self.frames.push(image.to_pl());
+        self.signatures.push(signature);
+        self.timestamps.push(ts);
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+}
+
+// Parts of the pipeline the renderer disables or replaces by a simpler path
+// when the GPU lacks what they need
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RenderStage {
+    // No renderable texture format, everything needing an intermediate
+    // texture is skipped
+    Fbo,
+    // Intermediate textures fell back to 8 bits per component
+    HighBitDepth,
+    // Configured scalers replaced by the builtin bilinear sampling
+    Sampling,
+    // Polar scalers run through the slower fragment shader path, without
+    // compute shaders
+    PolarCompute,
+    Debanding,
+    LinearScaling,
+    PeakDetect,
+    // HDR content is clipped instead of tone mapped
+    ToneMapping,
+    Lut3D,
+    FrameMixing,
+    // Blue noise and ordered LUT dithering need a linearly sampled float
+    // texture format for their matrix
+    Dithering,
+    Overlays,
+}
+
+impl fmt::Display for RenderStage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            RenderStage::Fbo => "intermediate textures",
+            RenderStage::HighBitDepth => "high bit depth textures",
+            RenderStage::Sampling => "custom scalers",
+            RenderStage::PolarCompute => "compute shader polar scalers",
+            RenderStage::Debanding => "debanding",
+            RenderStage::LinearScaling => "linear light scaling",
+            RenderStage::PeakDetect => "peak detection",
+            RenderStage::ToneMapping => "tone mapping",
+            RenderStage::Lut3D => "3DLUT",
+            RenderStage::FrameMixing => "frame mixing",
+            RenderStage::Dithering => "dithering",
+            RenderStage::Overlays => "overlays",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// The stage a message logged by the renderer reports as disabled or fallen
+// back from, if any. More specific words are checked first, e.g. a failed
+// dither LUT is about dithering rather than a 3DLUT.
+fn disabled_stage(message: &str) -> Option<RenderStage> {
+    const KEYWORDS: &[(&str, RenderStage)] = &[
+        ("dither", RenderStage::Dithering),
+        ("peak", RenderStage::PeakDetect),
+        ("tone", RenderStage::ToneMapping),
+        ("hdr", RenderStage::ToneMapping),
+        ("3dlut", RenderStage::Lut3D),
+        ("polar", RenderStage::PolarCompute),
+        ("compute", RenderStage::PolarCompute),
+        ("deband", RenderStage::Debanding),
+        ("linear", RenderStage::LinearScaling),
+        ("mix", RenderStage::FrameMixing),
+        ("overlay", RenderStage::Overlays),
+        ("fbo", RenderStage::Fbo),
+        ("8 bit", RenderStage::HighBitDepth),
+        ("8-bit", RenderStage::HighBitDepth),
+        ("scal", RenderStage::Sampling),
+        ("sampl", RenderStage::Sampling),
+    ];
+
+    let message = message.to_ascii_lowercase();
+    let disabled = ["disabl", "fall back", "falling back", "failed"]
+        .iter()
+        .any(|word| message.contains(word));
+    if !disabled {
+        return None;
+    }
+
+    KEYWORDS
+        .iter()
+        .find(|(keyword, _)| message.contains(keyword))
+        .map(|(_, stage)| *stage)
+}
+
+// Stages the renderer disabled or fell back from since it was created or its
+// errors last reset, as libplacebo logged them. Like libplacebo, which keeps
+// a stage disabled once it fails, the report only grows.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RenderReport {
+    disabled: Vec<RenderStage>,
+}
+
+impl RenderReport {
+    pub fn disabled(&self) -> &[RenderStage] {
+        &self.disabled
+    }
+
+    pub fn is_disabled(&self, stage: RenderStage) -> bool {
+        self.disabled.contains(&stage)
+    }
+
+    // Everything asked for by the params was applied
+    pub fn is_complete(&self) -> bool {
+        self.disabled.is_empty()
+    }
+
+    fn add(&mut self, stage: RenderStage) {
+        if !self.disabled.contains(&stage) {
+            self.disabled.push(stage);
+        }
+    }
+
+    fn add_messages(&mut self, messages: &[String]) {
+        for stage in messages.iter().filter_map(|m| disabled_stage(m)) {
+            self.add(stage);
+        }
+    }
+}
+
+fn is_polar(filter: *const pl_filter_config) -> bool {
+    !filter.is_null() && unsafe { (*filter).polar }
+}
+
+// Renders in the context it was created in, whose log reports the stages
+// the renderer disables
+pub struct Renderer<'ctx> {
     rr: *mut pl_renderer,
+    ctx: &'ctx Context,
+    gpu: Gpu,
+    report: RefCell<RenderReport>,
+    rotate_90: RefCell<Option<Hook>>,
+    rotate_270: RefCell<Option<Hook>>,
 }
 
-impl Renderer {
-    pub fn new(ctx: &Context, gpu: &Gpu) -> Self {
-        let rr =
-            unsafe { pl_renderer_create(ctx.get_mut_ptr(), gpu.get_ptr()) };
+impl<'ctx> Renderer<'ctx> {
+    pub fn new(ctx: &'ctx Context, gpu: &Gpu) -> Self {
+        let mut renderer = Renderer {
+            rr: null_mut(),
+            ctx,
+            gpu: gpu.clone(),
+            report: RefCell::new(Default::default()),
+            rotate_90: RefCell::new(None),
+            rotate_270: RefCell::new(None),
+        };
+        renderer.create();
+
+        renderer
+    }
+
+    // Creating the renderer already logs the lack of a usable intermediate
+    // texture format
+    fn create(&mut self) {
+        let (ctx, gpu) = (self.ctx, self.gpu.get_ptr());
+        let (rr, messages) = ctx.capture_log(&LogLevel::LOG_WARN, || unsafe {
+            pl_renderer_create(ctx.get_mut_ptr(), gpu)
+        });
         assert!(!rr.is_null());
 
-        Renderer { rr }
+        self.rr = rr;
+        self.report.borrow_mut().add_messages(&messages);
+    }
+
+    pub fn errors(&self) -> RenderReport {
+        self.report.borrow().clone()
+    }
+
+    // Lets the renderer try the disabled stages again. This libplacebo has
+    // no way to re-enable them one by one, so the renderer is recreated and
+    // its caches are lost.
+    pub fn reset_errors(&mut self) {
+        unsafe {
+            pl_renderer_destroy(&mut self.rr);
+        }
+        *self.report.borrow_mut() = Default::default();
+        self.create();
+    }
+
+    // Stages the renderer is expected to skip with these params, making the
+    // same decisions against the GPU limits libplacebo makes when setting up
+    // a pass. This is only a check before rendering, stages failing for other
+    // reasons are missed and `errors` reports what actually happened. Frame
+    // mixing only applies to `render_image_mix`.
+    pub fn preflight(&self, params: &RenderParams) -> Vec<RenderStage> {
+        let params = params.to_pl();
+        let limits = unsafe { (*self.gpu.get_ptr()).limits };
+        let compute = limits.max_shmem_size > 0 && limits.max_group_threads > 0;
+        let find = |type_: FmtType, depth: usize| {
+            self.gpu
+                .find_fmt(&type_, 4, depth, 0, &FmtCaps::FMT_CAP_RENDERABLE)
+                .is_some()
+        };
+
+        let mut expected = RenderReport::default();
+        if !find(FmtType::FMT_FLOAT, 16) && !find(FmtType::FMT_UNORM, 16) {
+            if find(FmtType::FMT_UNORM, 8) {
+                expected.add(RenderStage::HighBitDepth);
+            } else {
+                expected.add(RenderStage::Fbo);
+                if !params.upscaler.is_null() || !params.downscaler.is_null() {
+                    expected.add(RenderStage::Sampling);
+                }
+                if !params.deband_params.is_null() {
+                    expected.add(RenderStage::Debanding);
+                }
+                if !params.disable_linear_scaling {
+                    expected.add(RenderStage::LinearScaling);
+                }
+                if !params.frame_mixer.is_null() {
+                    expected.add(RenderStage::FrameMixing);
+                }
+            }
+        }
+
+        if !compute {
+            if !params.peak_detect_params.is_null() {
+                expected.add(RenderStage::PeakDetect);
+            }
+            if is_polar(params.upscaler) || is_polar(params.downscaler) {
+                expected.add(RenderStage::PolarCompute);
+            }
+        }
+
+        let lut3d = !params.lut3d_params.is_null() || params.force_3dlut;
+        if lut3d && limits.max_tex_3d_dim == 0 {
+            expected.add(RenderStage::Lut3D);
+        }
+
+        if !params.dither_params.is_null() {
+            let method = unsafe { (*params.dither_params).method };
+            let lut = !matches!(
+                method,
+                pl_dither_method::PL_DITHER_ORDERED_FIXED
+                    | pl_dither_method::PL_DITHER_WHITE_NOISE
+            );
+            let lut_fmt = self.gpu.find_fmt(
+                &FmtType::FMT_FLOAT,
+                1,
+                16,
+                0,
+                &FmtCaps::FMT_CAP_LINEAR,
+            );
+            if lut && lut_fmt.is_none() {
+                expected.add(RenderStage::Dithering);
+            }
+        }
+
+        expected.disabled
+    }
+
+    // Quarter turns transpose the image with a hook run before scaling, built
+    // on first use. Flips and half turns only need flipped rects.
+    fn rotated_params(
+        &self,
+        rotation: Rotation,
+        params: &RenderParams,
+    ) -> Result<RenderParams> {
+        let (cache, shader) = match rotation {
+            Rotation::Rotate90 => (&self.rotate_90, ROTATE_90_SHADER),
+            Rotation::Rotate270 => (&self.rotate_270, ROTATE_270_SHADER),
+            _ => return Ok(params.clone()),
+        };
+
+        let mut cache = cache.borrow_mut();
+        if cache.is_none() {
+            let shader = UserShader::parse(&self.gpu, shader)?;
+            *cache = Some(shader.into_hook());
+        }
+
+        let mut params = params.clone();
+        params.prepend_hook(cache.as_ref().unwrap());
+        Ok(params)
     }
 
     pub fn flush_cache(&mut self) {
@@ -312,27 +1263,219 @@ impl Renderer {
         }
     }
 
+    // Runs a render call, recording the stages it logs as disabled
+    fn report(&self, render: impl FnOnce() -> bool) -> Result<RenderReport> {
+        let (ok, messages) = self.ctx.capture_log(&LogLevel::LOG_WARN, render);
+        self.report.borrow_mut().add_messages(&messages);
+
+        if ok {
+            Ok(self.errors())
+        } else {
+            Err(Error::RenderFailed)
+        }
+    }
+
     pub fn render_image(
         &self,
         image: &Image,
         target: &RenderTarget,
         params: &RenderParams,
-    ) -> bool {
-        unsafe {
+    ) -> Result<RenderReport> {
+        // The params own the hooks the raw params point to
+        let params = self.rotated_params(image.rotation(), params)?;
+        let pl_params = params.to_pl();
+
+        self.report(|| unsafe {
             pl_render_image(
                 self.rr,
-                &image.img,
-                &target.target,
-                &params.params,
+                &image.to_pl(),
+                &target.to_pl(),
+                &pl_params,
             )
-        }
+        })
+    }
+
+    pub fn render_image_mix(
+        &self,
+        mix: &FrameMix,
+        target: &RenderTarget,
+        params: &RenderParams,
+    ) -> Result<RenderReport> {
+        let params = self.rotated_params(mix.rotation, params)?;
+        let pl_params = params.to_pl();
+
+        let frame_mix = pl_frame_mix {
+            num_frames: mix.frames.len() as i32,
+            frames: mix.frames.as_ptr(),
+            signatures: mix.signatures.as_ptr(),
+            timestamps: mix.timestamps.as_ptr(),
+            vsync_duration: mix.vsync_duration,
+        };
+
+        self.report(|| unsafe {
+            pl_render_image_mix(
+                self.rr,
+                &frame_mix,
+                &target.to_pl(),
+                &pl_params,
+            )
+        })
     }
 }
 
-impl Drop for Renderer {
+impl<'ctx> Drop for Renderer<'ctx> {
     fn drop(&mut self) {
         unsafe {
             pl_renderer_destroy(&mut self.rr);
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn src_rect(image: &Image) -> [f32; 4] {
+        let rect = image.to_pl().src_rect;
+        [rect.x0, rect.y0, rect.x1, rect.y1]
+    }
+
+    fn dst_rect(target: &RenderTarget) -> [i32; 4] {
+        let rect = target.to_pl().dst_rect;
+        [rect.x0, rect.y0, rect.x1, rect.y1]
+    }
+
+    #[test]
+    fn image_rects_flip_and_mirror() {
+        let mut image: Image = Default::default();
+        image.set_width(8);
+        image.set_height(4);
+        assert_eq!(src_rect(&image), [0.0, 0.0, 8.0, 4.0]);
+        image.set_mirror(true);
+        assert_eq!(src_rect(&image), [8.0, 0.0, 0.0, 4.0]);
+
+        image.set_src_rect(&Rect2DF::new(2.0, 1.0, 6.0, 3.0));
+        assert_eq!(src_rect(&image), [6.0, 1.0, 2.0, 3.0]);
+        image.set_mirror(false);
+        assert_eq!(src_rect(&image), [2.0, 1.0, 6.0, 3.0]);
+
+        // A half turn flips both axes, mirroring undoes the horizontal flip
+        image.set_rotation(Rotation::Rotate180);
+        assert_eq!(src_rect(&image), [6.0, 3.0, 2.0, 1.0]);
+        image.set_mirror(true);
+        assert_eq!(src_rect(&image), [2.0, 3.0, 6.0, 1.0]);
+
+        // Quarter turns are left to the transpose hook
+        image.set_rotation(Rotation::Rotate90);
+        assert_eq!(src_rect(&image), [6.0, 1.0, 2.0, 3.0]);
+    }
+
+    #[test]
+    fn target_rects_flip_and_mirror() {
+        let mut target: RenderTarget = Default::default();
+        target.set_dst_rect(&Rect2D::new(10, 20, 110, 70));
+        assert_eq!(dst_rect(&target), [10, 20, 110, 70]);
+
+        target.set_flipped(true);
+        assert_eq!(dst_rect(&target), [10, 70, 110, 20]);
+        target.set_mirror(true);
+        assert_eq!(dst_rect(&target), [110, 70, 10, 20]);
+        target.set_flipped(false);
+        assert_eq!(dst_rect(&target), [110, 20, 10, 70]);
+        let rect = target.dst_rect();
+        assert_eq!(
+            [rect.x0(), rect.y0(), rect.x1(), rect.y1()],
+            [10, 20, 110, 70]
+        );
+    }
+
+    #[test]
+    fn flipped_swapchain_rects_are_normalised() {
+        let mut target: RenderTarget = Default::default();
+        target.target.dst_rect = pl_rect2d {
+            x0: 10,
+            y0: 70,
+            x1: 110,
+            y1: 20,
+        };
+        unflip_rect(&mut target.target.dst_rect);
+        target.set_flipped(true);
+        let rect = target.dst_rect();
+        assert_eq!(
+            [rect.x0(), rect.y0(), rect.x1(), rect.y1()],
+            [10, 20, 110, 70]
+        );
+        assert_eq!(dst_rect(&target), [10, 70, 110, 20]);
+
+        // Mirroring a flipped frame flips both axes once
+        target.set_mirror(true);
+        assert_eq!(dst_rect(&target), [110, 70, 10, 20]);
+
+        let mut rect = pl_rect2d {
+            x0: 10,
+            y0: 20,
+            x1: 110,
+            y1: 70,
+        };
+        unflip_rect(&mut rect);
+        assert_eq!([rect.x0, rect.y0, rect.x1, rect.y1], [10, 20, 110, 70]);
+    }
+
+    #[test]
+    fn logged_messages_map_to_stages() {
+        let stage = |msg: &str| disabled_stage(msg);
+
+        assert_eq!(
+            stage("Failed dispatching peak detection, disabling"),
+            Some(RenderStage::PeakDetect)
+        );
+        assert_eq!(
+            stage("Disabling tone mapping"),
+            Some(RenderStage::ToneMapping)
+        );
+        assert_eq!(
+            stage("Failed linearizing HDR source, disabling"),
+            Some(RenderStage::ToneMapping)
+        );
+        assert_eq!(
+            stage("Failed creating dither LUT, disabling dithering"),
+            Some(RenderStage::Dithering)
+        );
+        assert_eq!(
+            stage("Failed updating 3DLUT.. disabling"),
+            Some(RenderStage::Lut3D)
+        );
+        assert_eq!(
+            stage("Found no renderable FBO format! Most features disabled"),
+            Some(RenderStage::Fbo)
+        );
+        assert_eq!(
+            stage("Failed rendering overlay texture!"),
+            Some(RenderStage::Overlays)
+        );
+        assert_eq!(
+            stage("Failed dispatching scaler.. disabling"),
+            Some(RenderStage::Sampling)
+        );
+        assert_eq!(stage("Peak detection buffer created"), None);
+    }
+
+    #[test]
+    fn reports_collect_each_stage_once() {
+        let mut report = RenderReport::default();
+        assert!(report.is_complete());
+
+        report.add_messages(&[
+            "Disabling peak detection".to_owned(),
+            "Failed dispatching peak detection, disabling".to_owned(),
+            "Disabling debanding".to_owned(),
+            "Rendering frame".to_owned(),
+        ]);
+        assert_eq!(
+            report.disabled(),
+            &[RenderStage::PeakDetect, RenderStage::Debanding]
+        );
+        assert!(report.is_disabled(RenderStage::Debanding));
+        assert!(!report.is_disabled(RenderStage::ToneMapping));
+    }
+}
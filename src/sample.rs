@@ -0,0 +1,70 @@
+use crate::gpu::*;
+
+#[cfg(feature = "half")]
+use half::f16;
+
+use std::mem;
+use std::slice;
+
+mod private {
+    pub trait Sealed {}
+}
+
+// A sample type planes can be uploaded from, pixels are expected in native
+// byte order. Sealed, since `as_bytes` relies on the implementors being plain
+// numbers without padding.
+pub trait Sample: private::Sealed + Copy + 'static {
+    const FMT_TYPE: FmtType;
+    const BITS: usize;
+
+    fn swap_bytes(self) -> Self;
+}
+
+macro_rules! sample {
+    ($type:ty, $fmt_type:ident, |$v:ident| $swap:expr) => {
+        impl private::Sealed for $type {}
+
+        impl Sample for $type {
+            const FMT_TYPE: FmtType = FmtType::$fmt_type;
+            const BITS: usize = mem::size_of::<$type>() * 8;
+
+            fn swap_bytes(self) -> Self {
+                let $v = self;
+                $swap
+            }
+        }
+    };
+}
+
+sample!(u8, FMT_UNORM, |v| v);
+sample!(u16, FMT_UNORM, |v| v.swap_bytes());
+sample!(f32, FMT_FLOAT, |v| f32::from_bits(v.to_bits().swap_bytes()));
+#[cfg(feature = "half")]
+sample!(f16, FMT_FLOAT, |v| f16::from_bits(v.to_bits().swap_bytes()));
+
+pub fn as_bytes<T: Sample>(samples: &[T]) -> &[u8] {
+    unsafe {
+        slice::from_raw_parts(
+            samples.as_ptr() as *const u8,
+            samples.len() * mem::size_of::<T>(),
+        )
+    }
+}
+
+// Converts samples stored as little endian to native byte order
+pub fn from_le<T: Sample>(samples: &mut [T]) {
+    if cfg!(target_endian = "big") {
+        for sample in samples {
+            *sample = sample.swap_bytes();
+        }
+    }
+}
+
+// Converts samples stored as big endian to native byte order
+pub fn from_be<T: Sample>(samples: &mut [T]) {
+    if cfg!(target_endian = "little") {
+        for sample in samples {
+            *sample = sample.swap_bytes();
+        }
+    }
+}
@@ -0,0 +1,261 @@
+use crate::colorspace::*;
+use crate::context::*;
+use crate::error::*;
+use crate::gpu::*;
+use crate::*;
+
+use libplacebo_sys::*;
+
+use std::any::Any;
+use std::cell::RefCell;
+use std::ffi::{c_void, CString};
+use std::fs;
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::rc::Rc;
+
+create_enum!(
+    HookStage,
+    pl_hook_stage,
+    (
+        HOOK_RGB_INPUT,
+        HOOK_LUMA_INPUT,
+        HOOK_CHROMA_INPUT,
+        HOOK_ALPHA_INPUT,
+        HOOK_XYZ_INPUT,
+        HOOK_CHROMA_SCALED,
+        HOOK_ALPHA_SCALED,
+        HOOK_NATIVE,
+        HOOK_RGB,
+        HOOK_LINEAR,
+        HOOK_SIGMOID,
+        HOOK_PRE_OVERLAY,
+        HOOK_PRE_KERNEL,
+        HOOK_POST_KERNEL,
+        HOOK_SCALED,
+        HOOK_OUTPUT,
+    )
+);
+
+impl HookStage {
+    // Stages after scaling hand over a shader, the others a texture
+    fn takes_color(&self) -> bool {
+        matches!(self, HookStage::HOOK_SCALED | HookStage::HOOK_OUTPUT)
+    }
+}
+
+pub struct HookParams {
+    pub stage: HookStage,
+    pub components: usize,
+    pub width: f32,
+    pub height: f32,
+    pub repr: ColorRepr,
+    pub color: ColorSpace,
+}
+
+// A pass run by the renderer at a given stage of its pipeline. The GLSL
+// returned is the body of a function modifying `vec4 color`, which holds
+// the current texture sampled at the pixel being processed.
+pub trait RenderHook {
+    fn stage(&self) -> HookStage;
+
+    // Returning None leaves the stage untouched
+    fn glsl(&mut self, params: &HookParams) -> Option<String>;
+
+    // Called when the renderer discards its state, e.g. on seeks
+    fn reset(&mut self) {}
+}
+
+struct RustHook {
+    hook: pl_hook,
+    stage: HookStage,
+    imp: RefCell<Box<dyn RenderHook>>,
+}
+
+unsafe extern "C" fn reset_hook(priv_: *mut c_void) {
+    let rust_hook = &*(priv_ as *const RustHook);
+    if let Ok(mut imp) = rust_hook.imp.try_borrow_mut() {
+        // A panic can't unwind into libplacebo, the hook just keeps its state
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| imp.reset()));
+    }
+}
+
+unsafe extern "C" fn run_hook(
+    priv_: *mut c_void,
+    params: *const pl_hook_params,
+) -> pl_hook_res {
+    let rust_hook = &*(priv_ as *const RustHook);
+    let params = &*params;
+    let mut res: pl_hook_res = mem::zeroed();
+
+    let hook_params = HookParams {
+        stage: rust_hook.stage,
+        components: params.components as usize,
+        width: params.rect.x1 - params.rect.x0,
+        height: params.rect.y1 - params.rect.y0,
+        repr: ColorRepr::from_pl(params.repr),
+        color: ColorSpace::from_pl(params.color),
+    };
+
+    // A panic can't unwind into libplacebo, it fails the pass instead
+    let body = match rust_hook.imp.try_borrow_mut() {
+        Ok(mut imp) => {
+            panic::catch_unwind(AssertUnwindSafe(|| imp.glsl(&hook_params)))
+        }
+        Err(_) => Ok(None),
+    };
+    let body = match body {
+        Ok(body) => body,
+        Err(_) => {
+            res.failed = true;
+            return res;
+        }
+    };
+    let body = match body.map(CString::new) {
+        Some(Ok(body)) => body,
+        Some(Err(_)) => {
+            res.failed = true;
+            return res;
+        }
+        None => return res,
+    };
+
+    let takes_color = rust_hook.stage.takes_color();
+    let mut sh = if takes_color {
+        params.sh
+    } else {
+        let mut sh = pl_dispatch_begin(params.dispatch);
+        let mut src: pl_sample_src = mem::zeroed();
+        src.tex = params.tex;
+        if !pl_shader_sample_direct(sh, &src) {
+            pl_dispatch_abort(params.dispatch, &mut sh);
+            res.failed = true;
+            return res;
+        }
+        sh
+    };
+
+    let mut custom: pl_custom_shader = mem::zeroed();
+    custom.body = body.as_ptr();
+    custom.input = pl_shader_sig::PL_SHADER_SIG_COLOR;
+    custom.output = pl_shader_sig::PL_SHADER_SIG_COLOR;
+
+    if pl_shader_custom(sh, &custom) {
+        res.output = pl_hook_sig::PL_HOOK_SIG_COLOR;
+        res.sh = sh;
+    } else {
+        // The shader begun for texture stages is ours to release
+        if !takes_color {
+            pl_dispatch_abort(params.dispatch, &mut sh);
+        }
+        res.failed = true;
+    }
+
+    res
+}
+
+// A hook that can be registered on `RenderParams`, keeping whatever backs
+// the `pl_hook` alive for as long as any params refer to it
+#[derive(Clone)]
+pub struct Hook {
+    hook: *const pl_hook,
+    _owner: Rc<dyn Any>,
+}
+
+impl Hook {
+    pub fn new<H: RenderHook + 'static>(imp: H) -> Self {
+        let stage = imp.stage();
+        let mut rust_hook = Box::new(RustHook {
+            hook: unsafe { mem::zeroed() },
+            stage,
+            imp: RefCell::new(Box::new(imp)),
+        });
+
+        rust_hook.hook.stages = stage.to_pl_hook_stage();
+        rust_hook.hook.input = if stage.takes_color() {
+            pl_hook_sig::PL_HOOK_SIG_COLOR
+        } else {
+            pl_hook_sig::PL_HOOK_SIG_TEX
+        };
+        rust_hook.hook.priv_ = &*rust_hook as *const RustHook as *mut c_void;
+        rust_hook.hook.reset = Some(reset_hook);
+        rust_hook.hook.hook = Some(run_hook);
+
+        let hook = &rust_hook.hook as *const pl_hook;
+        Hook::from_owner(hook, Rc::new(rust_hook))
+    }
+
+    pub(crate) fn from_owner(hook: *const pl_hook, owner: Rc<dyn Any>) -> Self {
+        Hook {
+            hook,
+            _owner: owner,
+        }
+    }
+
+    pub(crate) fn get_ptr(&self) -> *const pl_hook {
+        self.hook
+    }
+}
+
+// An mpv style user shader (.hook/.glsl), attached to `RenderParams` as a
+// hook
+pub struct UserShader {
+    hook: *const pl_hook,
+}
+
+impl UserShader {
+    // Parses the shader, the errors libplacebo logs while doing so are
+    // returned in the error
+    pub fn new(ctx: &Context, gpu: &Gpu, shader: &str) -> Result<Self> {
+        let (res, errors) =
+            ctx.capture_errors(|| UserShader::parse(gpu, shader));
+        res.map_err(|err| {
+            if errors.is_empty() {
+                err
+            } else {
+                Error::InvalidShader(errors.join("; "))
+            }
+        })
+    }
+
+    pub fn from_file<P: AsRef<Path>>(
+        ctx: &Context,
+        gpu: &Gpu,
+        path: P,
+    ) -> Result<Self> {
+        let shader = fs::read_to_string(path)
+            .map_err(|err| Error::Io(err.to_string()))?;
+        UserShader::new(ctx, gpu, &shader)
+    }
+
+    pub(crate) fn parse(gpu: &Gpu, shader: &str) -> Result<Self> {
+        let hook = unsafe {
+            pl_mpv_user_shader_parse(
+                gpu.get_ptr(),
+                shader.as_ptr() as *const _,
+                shader.len(),
+            )
+        };
+        if hook.is_null() {
+            return Err(Error::InvalidShader(
+                "rejected by libplacebo".to_owned(),
+            ));
+        }
+
+        Ok(UserShader { hook })
+    }
+
+    pub fn into_hook(self) -> Hook {
+        let hook = self.hook;
+        Hook::from_owner(hook, Rc::new(self))
+    }
+}
+
+impl Drop for UserShader {
+    fn drop(&mut self) {
+        unsafe {
+            pl_mpv_user_shader_destroy(&mut self.hook);
+        }
+    }
+}
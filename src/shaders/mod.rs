@@ -1,2 +1,3 @@
 pub mod colorspace;
+pub mod custom;
 pub mod sampling;
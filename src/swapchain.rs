@@ -6,7 +6,9 @@ use crate::*;
 use libplacebo_sys::*;
 
 use std::default::Default;
+use std::marker::PhantomData;
 use std::ptr::null;
+use std::time::{Duration, Instant};
 
 pub type VkPresentMode = VkPresentModeKHR;
 pub type VkColorSpace = VkColorSpaceKHR;
@@ -81,39 +83,86 @@ create_complete_struct!(
 
 get_ptr!(SwapchainFrame, frame, pl_swapchain_frame);
 
-pub struct Swapchain {
+impl SwapchainFrame {
+    pub fn width(&self) -> usize {
+        if self.frame.fbo.is_null() {
+            return 0;
+        }
+        unsafe { (*self.frame.fbo).params.w as usize }
+    }
+
+    pub fn height(&self) -> usize {
+        if self.frame.fbo.is_null() {
+            return 0;
+        }
+        unsafe { (*self.frame.fbo).params.h as usize }
+    }
+}
+
+pub struct Swapchain<'a> {
     sw: *const pl_swapchain,
+    // Destroyed after the swapchain presenting to it
+    #[cfg(feature = "window")]
+    _surface: Option<Surface<'a>>,
+    inst: PhantomData<&'a VulkanInstance>,
 }
 
-impl Swapchain {
+impl<'a> Swapchain<'a> {
     pub fn new(vk: &Vulkan, params: &SwapchainParams) -> Self {
-        let sw = unsafe {
-            pl_vulkan_create_swapchain(vk.get_ptr(), &params.sw_params)
-        };
+        Swapchain::create(vk, &params.sw_params)
+    }
+
+    // A swapchain presenting to `surface`, which it keeps alive
+    #[cfg(feature = "window")]
+    pub fn with_surface(
+        vk: &Vulkan,
+        params: &SwapchainParams,
+        surface: Surface<'a>,
+    ) -> Self {
+        let mut sw_params = params.sw_params;
+        sw_params.surface = surface.handle() as VkSurfaceKHR;
+        let mut sw = Swapchain::create(vk, &sw_params);
+        sw._surface = Some(surface);
+        sw
+    }
+
+    fn create(vk: &Vulkan, sw_params: &pl_vulkan_swapchain_params) -> Self {
+        let sw = unsafe { pl_vulkan_create_swapchain(vk.get_ptr(), sw_params) };
         assert!(!sw.is_null());
 
-        Swapchain { sw }
+        Swapchain {
+            sw,
+            #[cfg(feature = "window")]
+            _surface: None,
+            inst: PhantomData,
+        }
     }
 
     pub fn latency(&self) -> usize {
         unsafe { pl_swapchain_latency(self.sw) as usize }
     }
 
-    pub fn resize(&self, width: usize, height: usize) -> (usize, usize) {
+    pub fn resize(
+        &self,
+        width: usize,
+        height: usize,
+    ) -> Option<(usize, usize)> {
         let mut w = width as i32;
         let mut h = height as i32;
         let ok = unsafe { pl_swapchain_resize(self.sw, &mut w, &mut h) };
-        assert!(ok);
-        (w as usize, h as usize)
+        if ok {
+            Some((w as usize, h as usize))
+        } else {
+            None
+        }
     }
 
     pub fn start_frame(&self, frame: &mut SwapchainFrame) -> bool {
         unsafe { pl_swapchain_start_frame(self.sw, &mut frame.frame) }
     }
 
-    pub fn submit_frame(&self) {
-        let ok = unsafe { pl_swapchain_submit_frame(self.sw) };
-        assert!(ok);
+    pub fn submit_frame(&self) -> bool {
+        unsafe { pl_swapchain_submit_frame(self.sw) }
     }
 
     pub fn swap_buffers(&self) {
@@ -123,10 +172,261 @@ impl Swapchain {
     }
 }
 
-impl Drop for Swapchain {
+impl<'a> Drop for Swapchain<'a> {
     fn drop(&mut self) {
         unsafe {
             pl_swapchain_destroy(&mut self.sw);
         }
     }
 }
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SwapchainEvent {
+    Ready,
+    // The frame is ready, the size is the one granted by the surface
+    Resized(usize, usize),
+    // The frame is usable, but a resize is scheduled for the next one
+    Suboptimal,
+    // No frame was acquired, retry on the next iteration
+    OutOfDate,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SwapchainStats {
+    pub frames: u64,
+    pub missed_frames: u64,
+    pub suboptimal_frames: u64,
+    // From start_frame to submit_frame
+    pub frame_time: Duration,
+    pub avg_frame_time: Duration,
+    // Between two consecutive swap_buffers
+    pub present_interval: Duration,
+    pub avg_present_interval: Duration,
+    pub refresh_interval: Option<Duration>,
+    pub latency: usize,
+}
+
+// Weight of the newest sample in the running averages
+const STATS_SMOOTHING: f64 = 0.1;
+
+fn smooth(avg: Duration, sample: Duration) -> Duration {
+    if avg == Duration::default() {
+        return sample;
+    }
+    let avg = avg.as_secs_f64();
+    let sample = sample.as_secs_f64();
+    Duration::from_secs_f64(avg + (sample - avg) * STATS_SMOOTHING)
+}
+
+// Vblanks skipped by a present taking `interval`, presents taking noticeably
+// longer than a refresh cycle skipped at least one
+fn missed_vblanks(interval: Duration, refresh: Duration) -> u64 {
+    let cycles = interval.as_secs_f64() / refresh.as_secs_f64();
+    if cycles > 1.5 {
+        cycles.round() as u64 - 1
+    } else {
+        0
+    }
+}
+
+// Adds the interval between two presents to the statistics. The refresh
+// estimate starts from the shorter of the first two intervals, so that a
+// first present missing a vblank doesn't double it, and is refined by the
+// presents that missed none.
+fn add_present_interval(
+    stats: &SwapchainStats,
+    interval: Duration,
+) -> SwapchainStats {
+    let mut stats = *stats;
+    let previous = stats.present_interval;
+    stats.present_interval = interval;
+    stats.avg_present_interval = smooth(stats.avg_present_interval, interval);
+
+    let refresh = match stats.refresh_interval {
+        Some(refresh) => refresh,
+        None if previous == Duration::default() => return stats,
+        None => {
+            let refresh = previous.min(interval);
+            stats.missed_frames += missed_vblanks(previous, refresh);
+            refresh
+        }
+    };
+
+    let missed = missed_vblanks(interval, refresh);
+    stats.missed_frames += missed;
+    stats.refresh_interval = Some(if missed > 0 {
+        refresh
+    } else if interval.as_secs_f64() * 1.5 < refresh.as_secs_f64() {
+        // The display got faster, or the estimate was off
+        interval
+    } else {
+        smooth(refresh, interval)
+    });
+
+    stats
+}
+
+// Window resizes are only recorded by `set_size`, the swapchain is resized
+// lazily by the next `start_frame` so a burst of events costs one recreation.
+pub struct SwapchainManager<'a> {
+    sw: Swapchain<'a>,
+    requested: (usize, usize),
+    size: (usize, usize),
+    pending: bool,
+    // A size change not reported yet, kept until a frame is ready
+    pending_resize: Option<(usize, usize)>,
+    stats: SwapchainStats,
+    frame_start: Option<Instant>,
+    last_present: Option<Instant>,
+}
+
+impl<'a> SwapchainManager<'a> {
+    pub fn new(sw: Swapchain<'a>, width: usize, height: usize) -> Self {
+        SwapchainManager {
+            sw,
+            requested: (width, height),
+            size: (0, 0),
+            pending: true,
+            pending_resize: None,
+            stats: Default::default(),
+            frame_start: None,
+            last_present: None,
+        }
+    }
+
+    pub fn set_size(&mut self, width: usize, height: usize) {
+        if self.requested != (width, height) {
+            self.requested = (width, height);
+            self.pending = true;
+        }
+    }
+
+    pub fn size(&self) -> (usize, usize) {
+        self.size
+    }
+
+    pub fn swapchain(&self) -> &Swapchain<'a> {
+        &self.sw
+    }
+
+    pub fn start_frame(
+        &mut self,
+        frame: &mut SwapchainFrame,
+    ) -> SwapchainEvent {
+        if self.pending {
+            let (w, h) = self.requested;
+            // A zero sized surface (e.g. a minimized window) cannot back a
+            // swapchain, keep the request around until it becomes visible.
+            if w == 0 || h == 0 {
+                return SwapchainEvent::OutOfDate;
+            }
+            match self.sw.resize(w, h) {
+                Some(size) => {
+                    if size != self.size {
+                        self.pending_resize = Some(size);
+                    }
+                    self.size = size;
+                    self.pending = false;
+                }
+                None => return SwapchainEvent::OutOfDate,
+            }
+        }
+
+        if !self.sw.start_frame(frame) {
+            self.pending = true;
+            return SwapchainEvent::OutOfDate;
+        }
+
+        self.frame_start = Some(Instant::now());
+
+        if (frame.width(), frame.height()) != self.size {
+            self.pending = true;
+            self.stats.suboptimal_frames += 1;
+            return SwapchainEvent::Suboptimal;
+        }
+
+        match self.pending_resize.take() {
+            Some((w, h)) => SwapchainEvent::Resized(w, h),
+            None => SwapchainEvent::Ready,
+        }
+    }
+
+    pub fn submit_frame(&mut self) -> bool {
+        let ok = self.sw.submit_frame();
+        if !ok {
+            self.pending = true;
+        }
+        if let Some(start) = self.frame_start.take() {
+            let elapsed = start.elapsed();
+            self.stats.frame_time = elapsed;
+            self.stats.avg_frame_time =
+                smooth(self.stats.avg_frame_time, elapsed);
+        }
+        ok
+    }
+
+    pub fn swap_buffers(&mut self) {
+        self.sw.swap_buffers();
+
+        let now = Instant::now();
+        self.stats.frames += 1;
+        if let Some(last) = self.last_present.replace(now) {
+            self.stats = add_present_interval(&self.stats, now - last);
+        }
+    }
+
+    pub fn stats(&self) -> SwapchainStats {
+        SwapchainStats {
+            latency: self.sw.latency(),
+            ..self.stats
+        }
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.stats = Default::default();
+        self.frame_start = None;
+        self.last_present = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn present(intervals: &[u64]) -> SwapchainStats {
+        intervals.iter().fold(Default::default(), |stats, ms| {
+            add_present_interval(&stats, Duration::from_millis(*ms))
+        })
+    }
+
+    fn refresh_ms(stats: &SwapchainStats) -> f64 {
+        stats.refresh_interval.unwrap().as_secs_f64() * 1000.0
+    }
+
+    #[test]
+    fn steady_rate() {
+        let stats = present(&[16; 10]);
+
+        assert_eq!(stats.missed_frames, 0);
+        assert!((refresh_ms(&stats) - 16.0).abs() < 1e-3);
+        assert_eq!(stats.present_interval, Duration::from_millis(16));
+    }
+
+    #[test]
+    fn single_miss() {
+        let stats = present(&[16, 16, 16, 33, 16, 16]);
+
+        assert_eq!(stats.missed_frames, 1);
+        assert!((refresh_ms(&stats) - 16.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn miss_on_the_first_interval() {
+        let stats = present(&[33]);
+        assert_eq!(stats.refresh_interval, None);
+
+        let stats = present(&[33, 16, 16, 50]);
+        assert_eq!(stats.missed_frames, 1 + 2);
+        assert!((refresh_ms(&stats) - 16.0).abs() < 1e-3);
+    }
+}
@@ -1,47 +1,49 @@
+use crate::error::*;
+use crate::format::*;
 use crate::gpu::*;
 use crate::renderer::*;
+use crate::sample::*;
 use crate::*;
 
 use libplacebo_sys::*;
 
 use std::ffi::c_void;
 use std::ptr::null;
+use std::slice;
 
-set_struct!(PlaneData, plane_data, pl_plane_data);
-default_struct!(
-    PlaneData,
-    plane_data,
-    pl_plane_data,
-    (
-        type_,
-        width,
-        height,
-        component_size,
-        component_pad,
-        component_map,
-        pixel_stride,
-        row_stride,
-        pixels,
-        buf,
-        buf_offset,
-    ),
-    (
-        pl_fmt_type::PL_FMT_UNKNOWN,
-        0,
-        0,
-        [0; 4],
-        [0; 4],
-        [0; 4],
-        0,
-        0,
-        null(),
-        null(),
-        0,
-    )
-);
+#[derive(Clone)]
+pub struct PlaneData<'a> {
+    plane_data: pl_plane_data,
+    pixels: Option<&'a [u8]>,
+    buf: Option<&'a Buf>,
+}
+
+impl<'a> Default for PlaneData<'a> {
+    fn default() -> Self {
+        let plane_data = pl_plane_data {
+            type_: pl_fmt_type::PL_FMT_UNKNOWN,
+            width: 0,
+            height: 0,
+            component_size: [0; 4],
+            component_pad: [0; 4],
+            component_map: [0; 4],
+            pixel_stride: 0,
+            row_stride: 0,
+            pixels: null(),
+            buf: null(),
+            buf_offset: 0,
+        };
+
+        PlaneData {
+            plane_data,
+            pixels: None,
+            buf: None,
+        }
+    }
+}
 
 set_params!(
-    PlaneData,
+    PlaneData<'a>,
     plane_data,
     (
         type_,
@@ -52,21 +54,10 @@ set_params!(
         component_map,
         pixel_stride,
         row_stride,
-        pixels,
-        buf,
         buf_offset,
     ),
     (
-        &FmtType,
-        usize,
-        usize,
-        &[i32; 4],
-        &[i32; 4],
-        &[i32; 4],
-        usize,
-        usize,
-        &[u8],
-        &Buf,
+        &FmtType, usize, usize, &[i32; 4], &[i32; 4], &[i32; 4], usize, usize,
         usize,
     ),
     (
@@ -78,26 +69,165 @@ set_params!(
         *component_map as [i32; 4],
         pixel_stride as usize,
         row_stride as usize,
-        pixels.as_ptr() as *const c_void,
-        buf.get_ptr(),
         buf_offset as usize,
     )
 );
 
-impl PlaneData {
+impl<'a> PlaneData<'a> {
+    pub fn set_pixels(&mut self, pixels: &'a [u8]) {
+        self.pixels = Some(pixels);
+        self.plane_data.pixels = pixels.as_ptr() as *const c_void;
+    }
+
+    // Sets the pixels from typed samples, the component type and size are
+    // taken from the sample type
+    pub fn set_samples<T: Sample>(&mut self, samples: &'a [T]) {
+        self.set_pixels(as_bytes(samples));
+        self.plane_data.type_ = T::FMT_TYPE.to_pl_fmt_type();
+
+        let data = &mut self.plane_data;
+        if data.component_size.iter().all(|size| *size <= 0) {
+            data.component_size[0] = T::BITS as i32;
+        } else {
+            for size in data.component_size.iter_mut().filter(|s| **s > 0) {
+                *size = T::BITS as i32;
+            }
+        }
+    }
+
+    pub fn set_buf(&mut self, buf: &'a Buf) {
+        self.buf = Some(buf);
+        self.plane_data.buf = buf.get_ptr();
+    }
+
+    pub fn width(&self) -> usize {
+        self.plane_data.width as usize
+    }
+
+    pub fn height(&self) -> usize {
+        self.plane_data.height as usize
+    }
+
+    // Texture format the plane would be uploaded to, None if the GPU has no
+    // format matching the plane's components
+    pub fn find_fmt(&self, gpu: &Gpu) -> Option<Fmt> {
+        let mut out_map = [0; 4];
+        let fmt = unsafe {
+            pl_plane_find_fmt(
+                gpu.get_ptr(),
+                out_map.as_mut_ptr(),
+                &self.plane_data,
+            )
+        };
+        Fmt::from_ptr(fmt)
+    }
+
+    // Human readable component layout, e.g. "UNORM 8:8 map [1, 2]"
+    pub fn layout(&self) -> String {
+        let data = &self.plane_data;
+        let components = (0..4).filter(|i| data.component_size[*i] > 0);
+        let sizes: Vec<String> = components
+            .clone()
+            .map(|i| match data.component_pad[i] {
+                0 => data.component_size[i].to_string(),
+                pad => format!("{}+{}", pad, data.component_size[i]),
+            })
+            .collect();
+        let map: Vec<i32> = components.map(|i| data.component_map[i]).collect();
+
+        format!("{:?} {} map {:?}", data.type_, sizes.join(":"), map)
+    }
+
     pub fn data_from_mask(&mut self, mask: &mut [u64; 4]) {
         unsafe {
             pl_plane_data_from_mask(&mut self.plane_data, mask.as_mut_ptr());
         }
     }
 
+    // Number of bytes between the first and the last byte of the plane
+    pub fn data_size(&self) -> usize {
+        let data = &self.plane_data;
+        if data.width <= 0 || data.height <= 0 {
+            return 0;
+        }
+        (data.height as usize - 1) * data.row_stride
+            + data.width as usize * data.pixel_stride
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        let data = &self.plane_data;
+        let width = data.width.max(0) as usize;
+        let height = data.height.max(0) as usize;
+
+        if width == 0 || height == 0 {
+            return Err(Error::InvalidDimensions { width, height });
+        }
+
+        let mut bits = 0;
+        for i in 0..4 {
+            if data.component_size[i] <= 0 {
+                continue;
+            }
+            let map = data.component_map[i];
+            if !(0..=3).contains(&map) {
+                return Err(Error::InvalidComponentMap { component: i, map });
+            }
+            bits += (data.component_pad[i].max(0) + data.component_size[i])
+                as usize;
+        }
+
+        if bits == 0 {
+            return Err(Error::NoComponents);
+        }
+
+        let min = (bits + 7) / 8;
+        if data.pixel_stride < min {
+            return Err(Error::PixelStrideTooSmall {
+                pixel_stride: data.pixel_stride,
+                min,
+            });
+        }
+
+        let min = width * data.pixel_stride;
+        if data.row_stride < min {
+            return Err(Error::RowStrideTooSmall {
+                row_stride: data.row_stride,
+                min,
+            });
+        }
+
+        if data.row_stride % data.pixel_stride != 0 {
+            return Err(Error::RowStrideMisaligned {
+                row_stride: data.row_stride,
+                pixel_stride: data.pixel_stride,
+            });
+        }
+
+        let len = match (self.pixels, self.buf) {
+            (Some(pixels), None) => pixels.len(),
+            (None, Some(buf)) => buf.size().saturating_sub(data.buf_offset),
+            (None, None) => return Err(Error::NoPixelData),
+            (Some(_), Some(_)) => return Err(Error::ConflictingPixelData),
+        };
+
+        let needed = self.data_size();
+        if len < needed {
+            return Err(Error::DataTooShort { len, needed });
+        }
+
+        Ok(())
+    }
+
     pub fn upload_plane(
         &self,
         gpu: &Gpu,
         out_plane: &mut Plane,
         tex: &mut Tex,
-    ) {
-        let mut tex_i = null();
+    ) -> Result<()> {
+        self.validate()?;
+
+        // An existing texture is recreated only if its parameters differ
+        let mut tex_i = tex.get_ptr();
         let ok = unsafe {
             pl_upload_plane(
                 gpu.get_ptr(),
@@ -107,6 +237,239 @@ impl PlaneData {
             )
         };
         tex.set_ptr(tex_i);
-        assert!(ok);
+
+        if ok {
+            Ok(())
+        } else {
+            Err(Error::UploadFailed)
+        }
+    }
+}
+
+// A ring of host mapped transfer buffers that frames can be written to
+// directly, without copying them into a `Buf` first. A buffer is handed out
+// again once the GPU is done uploading from it.
+pub struct UploadRing {
+    bufs: Vec<Buf>,
+    next: usize,
+}
+
+impl UploadRing {
+    pub fn new(gpu: &Gpu, slots: usize, size: usize) -> Result<Self> {
+        let mut params: BufParams = Default::default();
+        params.set_type_(&BufType::BUF_TEX_TRANSFER);
+        params.set_size(size);
+        params.set_host_mapped(true);
+        params.set_host_writable(true);
+
+        let mut bufs = Vec::with_capacity(slots.max(1));
+        for _ in 0..slots.max(1) {
+            let buf = Buf::try_new(gpu, &params)?;
+            if buf.mapped_ptr().is_null() {
+                return Err(Error::BufferCreationFailed);
+            }
+            bufs.push(buf);
+        }
+
+        Ok(UploadRing { bufs, next: 0 })
+    }
+
+    // Ring sized for frames of the given format
+    pub fn for_format(
+        gpu: &Gpu,
+        slots: usize,
+        format: PixelFormat,
+        width: usize,
+        height: usize,
+    ) -> Result<Self> {
+        let strides = format.default_strides(width);
+        let size = format.buffer_size(height, &strides);
+        UploadRing::new(gpu, slots, size)
+    }
+
+    pub fn slots(&self) -> usize {
+        self.bufs.len()
+    }
+
+    pub fn slot_size(&self) -> usize {
+        self.bufs[0].size()
+    }
+
+    // Returns the next buffer once it is idle, waiting at most `timeout`
+    // nanoseconds for the GPU to release it
+    pub fn acquire(&mut self, timeout: u64) -> Option<UploadSlot> {
+        let buf = &self.bufs[self.next];
+        if buf.poll(timeout) {
+            return None;
+        }
+        self.next = (self.next + 1) % self.bufs.len();
+
+        Some(UploadSlot { buf })
+    }
+}
+
+pub struct UploadSlot<'a> {
+    buf: &'a Buf,
+}
+
+impl<'a> UploadSlot<'a> {
+    pub fn buf(&self) -> &Buf {
+        self.buf
+    }
+
+    pub fn data(&mut self) -> &mut [u8] {
+        unsafe {
+            slice::from_raw_parts_mut(self.buf.mapped_ptr(), self.buf.size())
+        }
+    }
+
+    // Points a plane description at `offset` within this slot
+    pub fn plane_data(
+        &self,
+        mut plane: PlaneData<'a>,
+        offset: usize,
+    ) -> PlaneData<'a> {
+        plane.set_buf(self.buf);
+        plane.set_buf_offset(offset);
+        plane
+    }
+
+    // Describes a frame whose planes are stored back to back with the given
+    // row strides, along with the plane offsets to write the data at
+    pub fn frame(
+        &self,
+        format: PixelFormat,
+        width: usize,
+        height: usize,
+        strides: &[usize],
+    ) -> Result<(Vec<PlaneData<'a>>, Vec<usize>)> {
+        let ranges = format.plane_ranges(height, strides);
+        let needed = format.buffer_size(height, strides);
+        if self.buf.size() < needed {
+            return Err(Error::DataTooShort {
+                len: self.buf.size(),
+                needed,
+            });
+        }
+
+        let planes = format
+            .plane_data(width, height, strides)
+            .into_iter()
+            .zip(&ranges)
+            .map(|(plane, (offset, _))| self.plane_data(plane, *offset))
+            .collect();
+        let offsets = ranges.iter().map(|(offset, _)| *offset).collect();
+
+        Ok((planes, offsets))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rgb_plane<'a>(
+        width: usize,
+        height: usize,
+        row_stride: usize,
+    ) -> PlaneData<'a> {
+        let mut data: PlaneData = Default::default();
+        data.set_type_(&FmtType::FMT_UNORM);
+        data.set_width(width);
+        data.set_height(height);
+        data.set_component_size(&[8, 8, 8, 0]);
+        data.set_component_map(&[0, 1, 2, 0]);
+        data.set_pixel_stride(3);
+        data.set_row_stride(row_stride);
+        data
+    }
+
+    #[test]
+    fn validate_accepts_matching_pixels() {
+        let pixels = [0u8; 27];
+        let mut data = rgb_plane(4, 2, 15);
+        data.set_pixels(&pixels);
+
+        assert_eq!(data.data_size(), 27);
+        assert_eq!(data.validate(), Ok(()));
+    }
+
+    #[test]
+    fn validate_rejects_bad_layouts() {
+        let pixels = [0u8; 64];
+
+        let mut data = rgb_plane(0, 2, 12);
+        data.set_pixels(&pixels);
+        assert_eq!(
+            data.validate(),
+            Err(Error::InvalidDimensions {
+                width: 0,
+                height: 2
+            })
+        );
+
+        let mut data = rgb_plane(4, 2, 12);
+        data.set_pixels(&pixels);
+        data.set_component_map(&[0, 1, 4, 0]);
+        assert_eq!(
+            data.validate(),
+            Err(Error::InvalidComponentMap {
+                component: 2,
+                map: 4
+            })
+        );
+
+        let mut data = rgb_plane(4, 2, 12);
+        data.set_pixels(&pixels);
+        data.set_component_size(&[0; 4]);
+        assert_eq!(data.validate(), Err(Error::NoComponents));
+
+        let mut data = rgb_plane(4, 2, 12);
+        data.set_pixels(&pixels);
+        data.set_pixel_stride(2);
+        assert_eq!(
+            data.validate(),
+            Err(Error::PixelStrideTooSmall {
+                pixel_stride: 2,
+                min: 3
+            })
+        );
+
+        let mut data = rgb_plane(4, 2, 9);
+        data.set_pixels(&pixels);
+        assert_eq!(
+            data.validate(),
+            Err(Error::RowStrideTooSmall {
+                row_stride: 9,
+                min: 12
+            })
+        );
+
+        let mut data = rgb_plane(4, 2, 13);
+        data.set_pixels(&pixels);
+        assert_eq!(
+            data.validate(),
+            Err(Error::RowStrideMisaligned {
+                row_stride: 13,
+                pixel_stride: 3
+            })
+        );
+    }
+
+    #[test]
+    fn validate_checks_pixel_data() {
+        let data = rgb_plane(4, 2, 12);
+        assert_eq!(data.validate(), Err(Error::NoPixelData));
+
+        let pixels = [0u8; 23];
+        let mut data = rgb_plane(4, 2, 12);
+        data.set_pixels(&pixels);
+        assert_eq!(
+            data.validate(),
+            Err(Error::DataTooShort {
+                len: 23,
+                needed: 24
+            })
+        );
     }
 }
@@ -70,12 +70,12 @@ macro_rules! default_struct {
 
 #[macro_export]
 macro_rules! set_params {
-    ($struct:ident, $param:ident,
+    ($struct:ident $(<$lt:lifetime>)?, $param:ident,
     ($($field_name:ident),*$(,)*),
     ($($field_type:ty),*$(,)*),
     ($($field_expr:expr),*$(,)*))
     => {
-            impl $struct {
+            impl$(<$lt>)? $struct$(<$lt>)? {
                 paste::item! {
                     $(
                         pub fn [<set_ $field_name>](&mut self, $field_name: $field_type) {
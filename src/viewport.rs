@@ -0,0 +1,286 @@
+use crate::common::*;
+use crate::error::*;
+use crate::gpu::*;
+use crate::renderer::*;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ViewportMode {
+    // Scale to fit inside the target, bordering the rest
+    Fit,
+    // Scale to cover the target, cropping the source
+    Fill,
+    // Ignore the aspect ratio and use the whole target
+    Stretch,
+    // Keep the source pixels unscaled, cropping or bordering as needed
+    CenterCrop,
+}
+
+// Places an image inside a target. `zoom` scales on top of the mode, `pan`
+// moves the visible part along each axis, from -1.0 to 1.0, when the image
+// is larger or smaller than the target.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Viewport {
+    mode: ViewportMode,
+    pixel_aspect: f32,
+    zoom: f32,
+    pan: (f32, f32),
+    border_color: [f32; 4],
+}
+
+impl Default for Viewport {
+    fn default() -> Self {
+        Viewport {
+            mode: ViewportMode::Fit,
+            pixel_aspect: 1.0,
+            zoom: 1.0,
+            pan: (0.0, 0.0),
+            border_color: [0.0, 0.0, 0.0, 1.0],
+        }
+    }
+}
+
+// Maps a source range onto a target range at the given scale, returning the
+// visible source range and the target range it covers
+fn place_axis(
+    src_len: f32,
+    dst_start: f32,
+    dst_len: f32,
+    scale: f32,
+    pan: f32,
+) -> ((f32, f32), (f32, f32)) {
+    let pan = pan.max(-1.0).min(1.0);
+    let scaled = src_len * scale;
+
+    if scaled <= dst_len {
+        let start = dst_start + (dst_len - scaled) / 2.0 * (1.0 + pan);
+        ((0.0, src_len), (start, start + scaled))
+    } else {
+        let visible = dst_len / scale;
+        let start = (src_len - visible) / 2.0 * (1.0 + pan);
+        ((start, start + visible), (dst_start, dst_start + dst_len))
+    }
+}
+
+impl Viewport {
+    pub fn new(mode: ViewportMode) -> Self {
+        Viewport {
+            mode,
+            ..Default::default()
+        }
+    }
+
+    pub fn set_mode(&mut self, mode: ViewportMode) {
+        self.mode = mode;
+    }
+
+    // Width of a source pixel relative to its height
+    pub fn set_pixel_aspect(&mut self, pixel_aspect: f32) {
+        if pixel_aspect > 0.0 {
+            self.pixel_aspect = pixel_aspect;
+        }
+    }
+
+    pub fn set_zoom(&mut self, zoom: f32) {
+        if zoom > 0.0 {
+            self.zoom = zoom;
+        }
+    }
+
+    pub fn set_pan(&mut self, x: f32, y: f32) {
+        self.pan = (x, y);
+    }
+
+    pub fn set_border_color(&mut self, border_color: &[f32; 4]) {
+        self.border_color = *border_color;
+    }
+
+    pub fn border_color(&self) -> [f32; 4] {
+        self.border_color
+    }
+
+    // Source and target rects showing a `src_width`x`src_height` image
+    // inside `dst`
+    pub fn rects(
+        &self,
+        src_width: usize,
+        src_height: usize,
+        dst: &Rect2D,
+    ) -> (Rect2DF, Rect2D) {
+        let (sw, sh) = (src_width as f32, src_height as f32);
+        let (dw, dh) = (dst.width() as f32, dst.height() as f32);
+        if sw <= 0.0 || sh <= 0.0 || dw <= 0.0 || dh <= 0.0 {
+            return (Rect2DF::new(0.0, 0.0, sw, sh), dst.clone());
+        }
+
+        let par = self.pixel_aspect;
+        let (scale_x, scale_y) = match self.mode {
+            ViewportMode::Stretch => (dw / sw, dh / sh),
+            ViewportMode::Fit => {
+                let scale = (dw / (sw * par)).min(dh / sh);
+                (scale * par, scale)
+            }
+            ViewportMode::Fill => {
+                let scale = (dw / (sw * par)).max(dh / sh);
+                (scale * par, scale)
+            }
+            ViewportMode::CenterCrop => (par, 1.0),
+        };
+
+        let ((sx0, sx1), (dx0, dx1)) = place_axis(
+            sw,
+            dst.x0() as f32,
+            dw,
+            scale_x * self.zoom,
+            self.pan.0,
+        );
+        let ((sy0, sy1), (dy0, dy1)) = place_axis(
+            sh,
+            dst.y0() as f32,
+            dh,
+            scale_y * self.zoom,
+            self.pan.1,
+        );
+
+        (
+            Rect2DF::new(sx0, sy0, sx1, sy1),
+            Rect2D::new(
+                dx0.round() as usize,
+                dy0.round() as usize,
+                dx1.round() as usize,
+                dy1.round() as usize,
+            ),
+        )
+    }
+
+    // Sets the image source rect and the target rect, covering the whole
+    // target texture. Rotated images are placed by their displayed size.
+    pub fn apply(&self, image: &mut Image, target: &mut RenderTarget) {
+        let (w, h) = match target.size() {
+            Some(size) => size,
+            None => return,
+        };
+        let (src_w, src_h) = image.display_size();
+        let mut viewport = *self;
+        let quarter_turn = image.rotation().is_quarter_turn();
+        if quarter_turn {
+            viewport.pixel_aspect = 1.0 / self.pixel_aspect;
+        }
+
+        let (mut src, dst) =
+            viewport.rects(src_w, src_h, &Rect2D::new(0, 0, w, h));
+        if quarter_turn {
+            // The renderer scales the source rect along with the rotated
+            // image, so it is given in the unrotated proportions
+            let (sx, sy) =
+                (src_h as f32 / src_w as f32, src_w as f32 / src_h as f32);
+            src = Rect2DF::new(
+                src.x0() * sx,
+                src.y0() * sy,
+                src.x1() * sx,
+                src.y1() * sy,
+            );
+        }
+        image.set_src_rect(&src);
+        target.set_dst_rect(&dst);
+    }
+
+    // The renderer leaves the target outside the rect it draws to untouched,
+    // clear it to the border color before rendering
+    pub fn clear_border(&self, gpu: &Gpu, target: &RenderTarget) -> Result<()> {
+        target.clear(gpu, &self.border_color)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn placed(
+        viewport: &Viewport,
+        src: (usize, usize),
+        dst: &Rect2D,
+    ) -> ([f32; 4], [usize; 4]) {
+        let (s, d) = viewport.rects(src.0, src.1, dst);
+        (
+            [s.x0(), s.y0(), s.x1(), s.y1()],
+            [d.x0(), d.y0(), d.x1(), d.y1()],
+        )
+    }
+
+    #[test]
+    fn modes_place_wide_images() {
+        let dst = Rect2D::new(0, 0, 100, 100);
+
+        assert_eq!(
+            placed(&Viewport::new(ViewportMode::Fit), (200, 100), &dst),
+            ([0.0, 0.0, 200.0, 100.0], [0, 25, 100, 75])
+        );
+        assert_eq!(
+            placed(&Viewport::new(ViewportMode::Fill), (200, 100), &dst),
+            ([50.0, 0.0, 150.0, 100.0], [0, 0, 100, 100])
+        );
+        assert_eq!(
+            placed(&Viewport::new(ViewportMode::Stretch), (200, 100), &dst),
+            ([0.0, 0.0, 200.0, 100.0], [0, 0, 100, 100])
+        );
+        assert_eq!(
+            placed(
+                &Viewport::new(ViewportMode::CenterCrop),
+                (200, 100),
+                &Rect2D::new(0, 0, 100, 200)
+            ),
+            ([50.0, 0.0, 150.0, 100.0], [0, 50, 100, 150])
+        );
+    }
+
+    #[test]
+    fn pan_moves_along_cropped_and_bordered_axes() {
+        let dst = Rect2D::new(0, 0, 100, 100);
+
+        let mut fill = Viewport::new(ViewportMode::Fill);
+        fill.set_pan(1.0, 0.0);
+        assert_eq!(placed(&fill, (200, 100), &dst).0[0], 100.0);
+        fill.set_pan(-1.0, 0.0);
+        assert_eq!(placed(&fill, (200, 100), &dst).0[0], 0.0);
+        fill.set_pan(2.0, 0.0);
+        assert_eq!(placed(&fill, (200, 100), &dst).0[0], 100.0);
+
+        let mut fit = Viewport::new(ViewportMode::Fit);
+        fit.set_pan(0.0, -1.0);
+        assert_eq!(placed(&fit, (200, 100), &dst).1, [0, 0, 100, 50]);
+    }
+
+    #[test]
+    fn zoom_and_pixel_aspect_scale_the_image() {
+        let mut fit = Viewport::new(ViewportMode::Fit);
+        fit.set_zoom(2.0);
+        assert_eq!(
+            placed(&fit, (200, 100), &Rect2D::new(0, 0, 100, 100)),
+            ([50.0, 0.0, 150.0, 100.0], [0, 0, 100, 100])
+        );
+
+        let mut fit = Viewport::new(ViewportMode::Fit);
+        fit.set_pixel_aspect(2.0);
+        assert_eq!(
+            placed(&fit, (100, 100), &Rect2D::new(0, 0, 200, 100)),
+            ([0.0, 0.0, 100.0, 100.0], [0, 0, 200, 100])
+        );
+        fit.set_pixel_aspect(0.0);
+        fit.set_zoom(-1.0);
+        assert_eq!((fit.pixel_aspect, fit.zoom), (2.0, 1.0));
+    }
+
+    #[test]
+    fn rects_follow_the_target_offset() {
+        let fit = Viewport::new(ViewportMode::Fit);
+
+        assert_eq!(
+            placed(&fit, (100, 100), &Rect2D::new(10, 20, 110, 220)),
+            ([0.0, 0.0, 100.0, 100.0], [10, 70, 110, 170])
+        );
+        assert_eq!(
+            placed(&fit, (0, 100), &Rect2D::new(10, 20, 110, 220)),
+            ([0.0, 0.0, 0.0, 100.0], [10, 20, 110, 220])
+        );
+    }
+}
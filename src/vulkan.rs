@@ -7,6 +7,15 @@ use libplacebo_sys::*;
 use std::ffi::CString;
 use std::ptr::null_mut;
 
+#[cfg(feature = "window")]
+use ash::vk::{self, Handle};
+#[cfg(feature = "window")]
+use raw_window_handle::{HasRawDisplayHandle, HasRawWindowHandle};
+#[cfg(feature = "window")]
+use std::ffi::CStr;
+#[cfg(feature = "window")]
+use std::marker::PhantomData;
+
 macro_rules! init_data {
     ($first:ident, $second:ident) => {
         let $first = Data {
@@ -76,6 +85,37 @@ impl VulkanInstanceParams {
     }
 
     extensions!(vk_inst_params);
+
+    #[cfg(feature = "window")]
+    pub fn add_window_extensions(
+        &mut self,
+        window: &impl HasRawDisplayHandle,
+    ) -> Result<(), vk::Result> {
+        let required = ash_window::enumerate_required_extensions(
+            window.raw_display_handle(),
+        )?;
+
+        let mut ext: Vec<String> = self
+            .c_ext
+            .c_str
+            .iter()
+            .map(|s| s.to_string_lossy().into_owned())
+            .collect();
+        for name in required {
+            let name = unsafe { CStr::from_ptr(*name) };
+            let name = name.to_string_lossy().into_owned();
+            if !ext.contains(&name) {
+                ext.push(name);
+            }
+        }
+
+        let ext: Vec<&str> = ext.iter().map(|s| s.as_str()).collect();
+        define_data!(ext, self.c_ext);
+        self.vk_inst_params.extensions = self.c_ext.c_sli;
+        self.vk_inst_params.num_extensions = ext.len() as i32;
+
+        Ok(())
+    }
 }
 
 pub struct VulkanInstance {
@@ -83,6 +123,8 @@ pub struct VulkanInstance {
 }
 
 impl VulkanInstance {
+    // `create_surface` needs the surface extensions of the window, added
+    // with `add_window_extensions`
     pub fn new(ctx: &Context, params: &VulkanInstanceParams) -> Self {
         let inst = unsafe {
             pl_vk_inst_create(ctx.get_mut_ptr(), &params.vk_inst_params)
@@ -95,6 +137,38 @@ impl VulkanInstance {
     pub fn instance(&self) -> usize {
         unsafe { (*self.inst).instance as usize }
     }
+
+    #[cfg(feature = "window")]
+    pub fn create_surface<W>(&self, window: &W) -> Result<Surface, vk::Result>
+    where
+        W: HasRawWindowHandle + HasRawDisplayHandle,
+    {
+        let entry = unsafe { ash::Entry::load() }
+            .map_err(|_| vk::Result::ERROR_INITIALIZATION_FAILED)?;
+        let instance = unsafe {
+            ash::Instance::load(
+                entry.static_fn(),
+                vk::Instance::from_raw(self.instance() as u64),
+            )
+        };
+        let surface = unsafe {
+            ash_window::create_surface(
+                &entry,
+                &instance,
+                window.raw_display_handle(),
+                window.raw_window_handle(),
+                None,
+            )?
+        };
+        let loader = ash::extensions::khr::Surface::new(&entry, &instance);
+
+        Ok(Surface {
+            surface,
+            loader,
+            _entry: entry,
+            inst: PhantomData,
+        })
+    }
 }
 
 impl Drop for VulkanInstance {
@@ -105,6 +179,31 @@ impl Drop for VulkanInstance {
     }
 }
 
+#[cfg(feature = "window")]
+pub struct Surface<'a> {
+    surface: vk::SurfaceKHR,
+    loader: ash::extensions::khr::Surface,
+    // The loader calls into the library the entry keeps loaded
+    _entry: ash::Entry,
+    inst: PhantomData<&'a VulkanInstance>,
+}
+
+#[cfg(feature = "window")]
+impl<'a> Surface<'a> {
+    pub fn handle(&self) -> u64 {
+        self.surface.as_raw()
+    }
+}
+
+#[cfg(feature = "window")]
+impl<'a> Drop for Surface<'a> {
+    fn drop(&mut self) {
+        unsafe {
+            self.loader.destroy_surface(self.surface, None);
+        }
+    }
+}
+
 pub struct VulkanParams {
     vk_params: pl_vulkan_params,
     c_ext: Data,
@@ -174,8 +273,24 @@ pub struct Vulkan {
 
 impl Vulkan {
     pub fn new(ctx: &Context, params: &VulkanParams) -> Self {
-        let vk =
-            unsafe { pl_vulkan_create(ctx.get_mut_ptr(), &params.vk_params) };
+        Vulkan::create(ctx, &params.vk_params)
+    }
+
+    // Picks a device able to present to `surface`, which only has to be
+    // alive while the device is created
+    #[cfg(feature = "window")]
+    pub fn with_surface(
+        ctx: &Context,
+        params: &VulkanParams,
+        surface: &Surface,
+    ) -> Self {
+        let mut vk_params = params.vk_params;
+        vk_params.surface = surface.handle() as VkSurfaceKHR;
+        Vulkan::create(ctx, &vk_params)
+    }
+
+    fn create(ctx: &Context, vk_params: &pl_vulkan_params) -> Self {
+        let vk = unsafe { pl_vulkan_create(ctx.get_mut_ptr(), vk_params) };
         assert!(!vk.is_null());
 
         Vulkan { vk }
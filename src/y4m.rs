@@ -0,0 +1,470 @@
+use crate::colorspace::*;
+use crate::format::*;
+
+use std::io::{self, BufRead, Write};
+
+const MAGIC: &str = "YUV4MPEG2";
+const FRAME: &str = "FRAME";
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Y4mHeader {
+    pub width: usize,
+    pub height: usize,
+    pub framerate: (u32, u32),
+    pub interlacing: char,
+    pub pixel_aspect: (u32, u32),
+    pub colorspace: String,
+    // XCOLORRANGE, None when the stream does not specify it
+    pub full_range: Option<bool>,
+}
+
+impl Y4mHeader {
+    pub fn new(width: usize, height: usize, format: PixelFormat) -> Self {
+        Y4mHeader {
+            width,
+            height,
+            framerate: (25, 1),
+            interlacing: 'p',
+            pixel_aspect: (1, 1),
+            colorspace: colorspace_tag(format).unwrap_or("420jpeg").to_owned(),
+            full_range: None,
+        }
+    }
+
+    pub fn parse(line: &str) -> io::Result<Self> {
+        let mut params = line.split_ascii_whitespace();
+        if params.next() != Some(MAGIC) {
+            return Err(invalid("Not a YUV4MPEG2 stream"));
+        }
+
+        let ratio = |v: &str| -> io::Result<(u32, u32)> {
+            let mut it = v.splitn(2, ':').map(|n| n.parse::<u32>());
+            match (it.next(), it.next()) {
+                (Some(Ok(n)), Some(Ok(d))) => Ok((n, d)),
+                _ => Err(invalid("Malformed ratio")),
+            }
+        };
+        let number = |v: &str| -> io::Result<usize> {
+            v.parse().map_err(|_| invalid("Malformed dimension"))
+        };
+
+        let mut header = Y4mHeader::new(0, 0, PixelFormat::Yuv420p);
+        for param in params {
+            let tag = match param.chars().next() {
+                Some(tag) => tag,
+                None => continue,
+            };
+            let value = &param[tag.len_utf8()..];
+            match tag {
+                'W' => header.width = number(value)?,
+                'H' => header.height = number(value)?,
+                'F' => header.framerate = ratio(value)?,
+                'A' => header.pixel_aspect = ratio(value)?,
+                'I' => header.interlacing = value.chars().next().unwrap_or('p'),
+                'C' => header.colorspace = value.to_owned(),
+                'X' => match value {
+                    "COLORRANGE=FULL" => header.full_range = Some(true),
+                    "COLORRANGE=LIMITED" => header.full_range = Some(false),
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+
+        if header.width == 0 || header.height == 0 {
+            return Err(invalid("Missing frame dimensions"));
+        }
+        header.format()?;
+
+        Ok(header)
+    }
+
+    pub fn format(&self) -> io::Result<PixelFormat> {
+        use PixelFormat::*;
+
+        let format = match self.colorspace.as_str() {
+            "420jpeg" | "420paldv" | "420mpeg2" | "420" => Yuv420p,
+            "422" => Yuv422p,
+            "444" => Yuv444p,
+            "420p10" => Yuv420p10,
+            "422p10" => Yuv422p10,
+            "444p10" => Yuv444p10,
+            "420p12" => Yuv420p12,
+            "422p12" => Yuv422p12,
+            "444p12" => Yuv444p12,
+            "420p16" => Yuv420p16,
+            "422p16" => Yuv422p16,
+            "444p16" => Yuv444p16,
+            "mono" => Gray,
+            "mono16" => Gray16,
+            _ => return Err(invalid("Unsupported colorspace")),
+        };
+
+        Ok(format)
+    }
+
+    // Y4M carries no matrix information, assume BT.601 for SD content
+    pub fn repr(&self) -> io::Result<ColorRepr> {
+        let format = self.format()?;
+        let mut repr = format.default_repr();
+
+        if format.is_yuv() && self.height <= 576 {
+            repr.set_sys(&ColorSystem::COLOR_SYSTEM_BT_601);
+        }
+        match self.full_range {
+            Some(true) => repr.set_levels(&ColorLevels::COLOR_LEVELS_PC),
+            Some(false) => repr.set_levels(&ColorLevels::COLOR_LEVELS_TV),
+            None => {}
+        }
+
+        Ok(repr)
+    }
+
+    pub fn chroma_location(&self) -> ChromaLocation {
+        match self.colorspace.as_str() {
+            "420jpeg" | "420" => ChromaLocation::CHROMA_CENTER,
+            "420paldv" => ChromaLocation::CHROMA_TOP_LEFT,
+            _ => ChromaLocation::CHROMA_LEFT,
+        }
+    }
+
+    // Only 8 bit 4:2:0 has tags for its chroma location, other formats
+    // accept the location they imply and any when they aren't subsampled
+    pub fn set_chroma_location(
+        &mut self,
+        location: &ChromaLocation,
+    ) -> io::Result<()> {
+        use ChromaLocation::*;
+
+        let format = self.format()?;
+        if format == PixelFormat::Yuv420p {
+            self.colorspace = match location {
+                CHROMA_CENTER => "420jpeg",
+                CHROMA_LEFT | CHROMA_UNKNOWN => "420mpeg2",
+                CHROMA_TOP_LEFT => "420paldv",
+                _ => return Err(invalid("Chroma location has no Y4M tag")),
+            }
+            .to_owned();
+        } else if format.chroma_shift() != (0, 0)
+            && location.to_pl_chroma_location()
+                != self.chroma_location().to_pl_chroma_location()
+        {
+            return Err(invalid("Chroma location has no Y4M tag"));
+        }
+
+        Ok(())
+    }
+
+    pub fn frame_size(&self) -> io::Result<usize> {
+        let format = self.format()?;
+        Ok((0..format.num_planes())
+            .map(|i| {
+                let (w, h) = format.plane_size(i, self.width, self.height);
+                w * h * format.pixel_stride(i)
+            })
+            .sum())
+    }
+}
+
+fn colorspace_tag(format: PixelFormat) -> Option<&'static str> {
+    use PixelFormat::*;
+
+    let tag = match format {
+        Yuv420p => "420jpeg",
+        Yuv422p => "422",
+        Yuv444p => "444",
+        Yuv420p10 => "420p10",
+        Yuv422p10 => "422p10",
+        Yuv444p10 => "444p10",
+        Yuv420p12 => "420p12",
+        Yuv422p12 => "422p12",
+        Yuv444p12 => "444p12",
+        Yuv420p16 => "420p16",
+        Yuv422p16 => "422p16",
+        Yuv444p16 => "444p16",
+        Gray => "mono",
+        Gray16 => "mono16",
+        _ => return None,
+    };
+
+    Some(tag)
+}
+
+impl std::fmt::Display for Y4mHeader {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{} W{} H{} F{}:{} I{} A{}:{} C{}",
+            MAGIC,
+            self.width,
+            self.height,
+            self.framerate.0,
+            self.framerate.1,
+            self.interlacing,
+            self.pixel_aspect.0,
+            self.pixel_aspect.1,
+            self.colorspace
+        )?;
+        match self.full_range {
+            Some(true) => write!(f, " XCOLORRANGE=FULL"),
+            Some(false) => write!(f, " XCOLORRANGE=LIMITED"),
+            None => Ok(()),
+        }
+    }
+}
+
+// Y4M stores samples wider than 8 bits as little endian, uploads expect
+// them in native order
+fn swap_samples(format: PixelFormat, plane: usize, data: &mut [u8]) {
+    if cfg!(target_endian = "big") && format.component_size(plane) == 16 {
+        for sample in data.chunks_exact_mut(2) {
+            sample.swap(0, 1);
+        }
+    }
+}
+
+pub struct Y4mReader<R: BufRead> {
+    reader: R,
+    header: Y4mHeader,
+    format: PixelFormat,
+}
+
+impl<R: BufRead> Y4mReader<R> {
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let header = Y4mHeader::parse(line.trim_end())?;
+        let format = header.format()?;
+
+        Ok(Y4mReader {
+            reader,
+            header,
+            format,
+        })
+    }
+
+    pub fn header(&self) -> &Y4mHeader {
+        &self.header
+    }
+
+    // Returns the planes of the next frame, tightly packed, or None at the
+    // end of the stream
+    pub fn read_frame(&mut self) -> io::Result<Option<Vec<Vec<u8>>>> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        if !line.starts_with(FRAME) {
+            return Err(invalid("Missing frame marker"));
+        }
+
+        let format = self.format;
+        let mut planes = Vec::with_capacity(format.num_planes());
+        for i in 0..format.num_planes() {
+            let (w, h) =
+                format.plane_size(i, self.header.width, self.header.height);
+            let mut data = vec![0; w * h * format.pixel_stride(i)];
+            self.reader.read_exact(&mut data)?;
+            swap_samples(format, i, &mut data);
+            planes.push(data);
+        }
+
+        Ok(Some(planes))
+    }
+}
+
+impl<R: BufRead> Iterator for Y4mReader<R> {
+    type Item = io::Result<Vec<Vec<u8>>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_frame().transpose()
+    }
+}
+
+pub struct Y4mWriter<W: Write> {
+    writer: W,
+    header: Y4mHeader,
+    format: PixelFormat,
+}
+
+impl<W: Write> Y4mWriter<W> {
+    pub fn new(mut writer: W, header: &Y4mHeader) -> io::Result<Self> {
+        let format = header.format()?;
+        writeln!(writer, "{}", header)?;
+
+        Ok(Y4mWriter {
+            writer,
+            header: header.clone(),
+            format,
+        })
+    }
+
+    pub fn write_frame(&mut self, planes: &[Vec<u8>]) -> io::Result<()> {
+        let format = self.format;
+        if planes.len() != format.num_planes() {
+            return Err(invalid("Wrong number of planes"));
+        }
+
+        writeln!(self.writer, "{}", FRAME)?;
+        for (i, plane) in planes.iter().enumerate() {
+            let (w, h) =
+                format.plane_size(i, self.header.width, self.header.height);
+            let size = w * h * format.pixel_stride(i);
+            if plane.len() < size {
+                return Err(invalid("Plane too short"));
+            }
+            let mut data = plane[..size].to_vec();
+            swap_samples(format, i, &mut data);
+            self.writer.write_all(&data)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Cursor;
+
+    #[test]
+    fn parse_header() {
+        let header = Y4mHeader::parse(
+            "YUV4MPEG2 W720 H480 F30000:1001 It A10:11 C420p10 XCOLORRANGE=FULL",
+        )
+        .unwrap();
+
+        assert_eq!((header.width, header.height), (720, 480));
+        assert_eq!(header.framerate, (30000, 1001));
+        assert_eq!(header.interlacing, 't');
+        assert_eq!(header.pixel_aspect, (10, 11));
+        assert_eq!(header.full_range, Some(true));
+        assert_eq!(header.format().unwrap(), PixelFormat::Yuv420p10);
+        assert!(matches!(
+            header.chroma_location(),
+            ChromaLocation::CHROMA_LEFT
+        ));
+    }
+
+    #[test]
+    fn parse_defaults() {
+        let header = Y4mHeader::parse("YUV4MPEG2 W4 H2").unwrap();
+
+        assert_eq!(header.format().unwrap(), PixelFormat::Yuv420p);
+        assert_eq!(header.framerate, (25, 1));
+        assert_eq!(header.full_range, None);
+        assert!(matches!(
+            header.chroma_location(),
+            ChromaLocation::CHROMA_CENTER
+        ));
+    }
+
+    #[test]
+    fn chroma_location_tags() {
+        let mut header = Y4mHeader::parse("YUV4MPEG2 W4 H2").unwrap();
+
+        header
+            .set_chroma_location(&ChromaLocation::CHROMA_LEFT)
+            .unwrap();
+        assert_eq!(header.colorspace, "420mpeg2");
+        header
+            .set_chroma_location(&ChromaLocation::CHROMA_TOP_LEFT)
+            .unwrap();
+        assert_eq!(header.colorspace, "420paldv");
+        assert!(header
+            .set_chroma_location(&ChromaLocation::CHROMA_BOTTOM_LEFT)
+            .is_err());
+
+        let mut header = Y4mHeader::parse("YUV4MPEG2 W4 H2 C420p10").unwrap();
+        assert!(header
+            .set_chroma_location(&ChromaLocation::CHROMA_LEFT)
+            .is_ok());
+        assert!(header
+            .set_chroma_location(&ChromaLocation::CHROMA_CENTER)
+            .is_err());
+
+        let mut header = Y4mHeader::parse("YUV4MPEG2 W4 H2 C444").unwrap();
+        assert!(header
+            .set_chroma_location(&ChromaLocation::CHROMA_CENTER)
+            .is_ok());
+        assert_eq!(header.colorspace, "444");
+    }
+
+    #[test]
+    fn parse_skips_unknown_parameters() {
+        let header = Y4mHeader::parse("YUV4MPEG2 W4 ÿx H2 Cmono").unwrap();
+
+        assert_eq!((header.width, header.height), (4, 2));
+        assert_eq!(header.format().unwrap(), PixelFormat::Gray);
+    }
+
+    #[test]
+    fn parse_rejects_malformed_headers() {
+        assert!(Y4mHeader::parse("YUV4MPEG W4 H2").is_err());
+        assert!(Y4mHeader::parse("YUV4MPEG2 W4").is_err());
+        assert!(Y4mHeader::parse("YUV4MPEG2 Wx H2").is_err());
+        assert!(Y4mHeader::parse("YUV4MPEG2 W4 H2 F25").is_err());
+        assert!(Y4mHeader::parse("YUV4MPEG2 W4 H2 C411").is_err());
+    }
+
+    #[test]
+    fn header_round_trip() {
+        let mut header = Y4mHeader::new(6, 4, PixelFormat::Yuv444p16);
+        header.full_range = Some(false);
+        let line = header.to_string();
+
+        assert_eq!(
+            line,
+            "YUV4MPEG2 W6 H4 F25:1 Ip A1:1 C444p16 XCOLORRANGE=LIMITED"
+        );
+        assert_eq!(Y4mHeader::parse(&line).unwrap(), header);
+    }
+
+    #[test]
+    fn frame_size() {
+        let header = Y4mHeader::new(5, 3, PixelFormat::Yuv420p);
+        assert_eq!(header.frame_size().unwrap(), 15 + 2 * 6);
+
+        let header = Y4mHeader::new(5, 3, PixelFormat::Yuv422p10);
+        assert_eq!(header.frame_size().unwrap(), 30 + 2 * 18);
+    }
+
+    #[test]
+    fn frames_round_trip() {
+        let header = Y4mHeader::new(4, 2, PixelFormat::Yuv420p10);
+        let planes = vec![
+            (0..16).collect::<Vec<u8>>(),
+            vec![1, 2, 3, 4],
+            vec![5, 6, 7, 8],
+        ];
+
+        let mut out = Vec::new();
+        {
+            let mut writer = Y4mWriter::new(&mut out, &header).unwrap();
+            writer.write_frame(&planes).unwrap();
+            writer.write_frame(&planes).unwrap();
+        }
+
+        let mut reader = Y4mReader::new(Cursor::new(out)).unwrap();
+        assert_eq!(reader.header(), &header);
+        let frames: Vec<_> = (&mut reader).map(|f| f.unwrap()).collect();
+        assert_eq!(frames, vec![planes.clone(), planes]);
+    }
+
+    #[test]
+    fn read_rejects_missing_frame_marker() {
+        let data = b"YUV4MPEG2 W2 H2 Cmono\nFRAM\n\0\0\0\0".to_vec();
+        let mut reader = Y4mReader::new(Cursor::new(data)).unwrap();
+
+        assert!(reader.read_frame().is_err());
+    }
+}